@@ -0,0 +1,77 @@
+//! Multi-monitor capture before and after dropping the PNG round-trip and capturing the
+//! displays in parallel. Run with `cargo bench --bench capture`. The displays are a `FakeBackend`,
+//! so the BGRA conversion of the real backend is measured on its own by `to_rgba`.
+
+use std::io::Cursor;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use image::{GenericImage, Rgba, RgbaImage};
+use progetto::myapp::capturelib::{capture_displays, composite, CaptureBackend, FakeBackend, Monitor, ScreenshotsBackend};
+
+const UHD: (u32, u32) = (3840, 2160);
+
+/// `displays` 4K displays side by side, the frame is made once and cloned by every capture.
+fn backend(displays: usize) -> FakeBackend {
+    let frame = RgbaImage::from_fn(UHD.0, UHD.1, |x, y| Rgba([(x % 251) as u8, (y % 251) as u8, 90, 255]));
+    FakeBackend::from_images(vec![frame; displays])
+}
+
+/// The frame as `screenshots` hands it over, before any conversion.
+fn grab(backend: &FakeBackend, monitor: &Monitor) -> screenshots::Image {
+    let frame = backend.capture_display(monitor).unwrap();
+    screenshots::Image::new(frame.width(), frame.height(), frame.into_raw())
+}
+
+/// What `ScreenshotsBackend::to_rgba` used to do before handing the pixels over.
+fn png_round_trip(image: screenshots::Image) -> RgbaImage {
+    let png = image.to_png().unwrap();
+    image::io::Reader::new(Cursor::new(png)).with_guessed_format().unwrap().decode().unwrap().to_rgba8()
+}
+
+/// The old compositing: one display after the other, through PNG, pixel by pixel.
+fn old_pipeline(backend: &FakeBackend, displays: &[Monitor]) -> RgbaImage {
+    let width = displays.iter().map(|m| m.x + m.width as i32).max().unwrap() as u32;
+    let mut img = RgbaImage::new(width, UHD.1);
+    for monitor in displays {
+        let capture = png_round_trip(grab(backend, monitor));
+        img.copy_from(&capture, monitor.x as u32, monitor.y as u32).unwrap();
+    }
+    img
+}
+
+fn to_rgba(c: &mut Criterion) {
+    let mut group = c.benchmark_group("to_rgba");
+    group.sample_size(10);
+    for (name, (width, height)) in [("1080p", (1920, 1080)), ("4k", UHD)] {
+        //screenshots::Image is not Clone, each batch wraps a copy of the same pixels
+        let pixels: Vec<u8> = (0..width * height * 4).map(|i| (i % 253) as u8).collect();
+        let screen = || screenshots::Image::new(width, height, pixels.clone());
+        group.bench_function(BenchmarkId::new("png_round_trip", name), |b| {
+            b.iter_batched(screen, png_round_trip, BatchSize::LargeInput)
+        });
+        group.bench_function(BenchmarkId::new("raw", name), |b| {
+            b.iter_batched(screen, |s| ScreenshotsBackend::to_rgba(s).unwrap(), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn three_monitors(c: &mut Criterion) {
+    let backend = backend(3);
+    let displays = backend.displays().unwrap();
+    let mut group = c.benchmark_group("3x4k");
+    group.sample_size(10);
+    group.bench_function("png_sequential", |b| b.iter(|| old_pipeline(&backend, &displays)));
+    group.bench_function("raw_sequential", |b| {
+        b.iter(|| {
+            let captures = displays.iter().map(|m| (*m, backend.capture_display(m).unwrap())).collect();
+            composite(captures).unwrap()
+        })
+    });
+    group.bench_function("raw_parallel", |b| {
+        b.iter(|| composite(capture_displays(&backend, displays.clone()).unwrap()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, to_rgba, three_monitors);
+criterion_main!(benches);
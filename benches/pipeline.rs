@@ -0,0 +1,177 @@
+//! Every stage between the capture and the saved file, on synthetic 1080p, 4K and three-display
+//! screens. Run with `cargo bench --bench pipeline`, the peak memory of each stage is printed first.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use egui::{pos2, vec2, Color32, Pos2, Stroke};
+use image::RgbaImage;
+use progetto::myapp::capturelib::{capture_displays, composite, CaptureBackend, FakeBackend, Monitor};
+use progetto::myapp::exportlib::{encode_jpeg, encode_png, JpegOptions, PngOptions};
+use progetto::myapp::giflib::{encode_gif, GifOptions};
+use progetto::myapp::imglib::load_image_from_memory;
+use progetto::myapp::paintlib::{ArrowHead, Painting, Redaction, Shapes, TextBox};
+
+/// Counts the bytes in use, to report the peak of each stage.
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Synthetic desktops: one display, or a 4K display between two 1080p ones.
+const SCREENS: [(&str, &[(u32, u32)]); 3] = [
+    ("1080p", &[(1920, 1080)]),
+    ("4k", &[(3840, 2160)]),
+    ("3_monitors", &[(1920, 1080), (3840, 2160), (1920, 1080)]),
+];
+
+/// The displays of a synthetic desktop, side by side. The frames are made here once,
+/// every capture clones them.
+fn backend(sizes: &[(u32, u32)]) -> FakeBackend {
+    let frames = sizes
+        .iter()
+        .map(|&(width, height)| RgbaImage::from_fn(width, height, |x, y| image::Rgba([(x % 251) as u8, (y % 251) as u8, 90, 255])))
+        .collect();
+    FakeBackend::from_images(frames)
+}
+
+fn captures(backend: &FakeBackend) -> Vec<(Monitor, RgbaImage)> {
+    capture_displays(backend, backend.displays().unwrap()).unwrap()
+}
+
+/// A desktop-like image: flat areas with some gradients, so encoders don't see noise.
+fn screenshot(backend: &FakeBackend) -> RgbaImage {
+    let mut img = composite(captures(backend)).unwrap();
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        *pixel = match (x / 64 + y / 48) % 4 {
+            0 => image::Rgba([240, 240, 240, 255]),
+            1 => image::Rgba([(x % 256) as u8, (y % 256) as u8, 128, 255]),
+            2 => image::Rgba([30, 30, 30, 255]),
+            _ => image::Rgba([40, 120, 200, 255]),
+        };
+    }
+    img
+}
+
+/// About twenty annotations of every kind, placed relative to the image size.
+fn annotated(img: &RgbaImage) -> Painting {
+    let (w, h) = (img.width() as f32, img.height() as f32);
+    let at = |x: f32, y: f32| pos2(x * w, y * h);
+    let stroke = Stroke::new(4., Color32::from_rgb(220, 40, 40));
+    let mut annotations = vec![];
+    let line: Vec<Pos2> = (0..400).map(|i| at(0.05 + i as f32 / 1000., 0.5 + (i as f32 / 20.).sin() * 0.1)).collect();
+    annotations.push((Shapes::None, line, stroke));
+    for i in 0..5 {
+        let offset = i as f32 * 0.08;
+        annotations.push((Shapes::Rect, vec![at(0.1 + offset, 0.1), at(0.15 + offset, 0.2)], stroke));
+        annotations.push((Shapes::Circle, vec![at(0.6 + offset / 2., 0.3), at(0.63 + offset / 2., 0.33)], stroke));
+        annotations.push((Shapes::Arrow(ArrowHead { double: i % 2 == 0, size: 20. }), vec![at(0.5, 0.9), at(0.3 + offset, 0.7)], stroke));
+    }
+    let text = TextBox { text: "Regression here".to_string(), size: 48., background: Some(Color32::BLACK) };
+    annotations.push((Shapes::Text(text), vec![at(0.6, 0.6)], Stroke::new(1., Color32::WHITE)));
+    for (i, redaction) in [Redaction::Pixelate, Redaction::Blur, Redaction::BlackBox].into_iter().enumerate() {
+        let x = 0.1 + i as f32 * 0.25;
+        annotations.push((Shapes::Redact(redaction), vec![at(x, 0.75), at(x + 0.15, 0.9)], stroke));
+    }
+    Painting::with_annotations(vec2(w, h), 1., annotations)
+}
+
+/// Runs `stage` once and returns how many more bytes were allocated at its peak.
+fn peak_memory<T>(stage: impl FnOnce() -> T) -> usize {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    drop(stage());
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+fn memory(_c: &mut Criterion) {
+    for (name, sizes) in SCREENS {
+        let backend = backend(sizes);
+        let img = screenshot(&backend);
+        let displays = captures(&backend);
+        let mut painting = annotated(&img);
+        //the stages take ownership of the image, copies are made before measuring
+        let (for_texture, for_editor) = (img.clone(), img.clone());
+        let stages = [
+            ("capture", peak_memory(|| captures(&backend))),
+            ("composite", peak_memory(|| composite(displays).unwrap())),
+            ("load_image_from_memory", peak_memory(|| load_image_from_memory(for_texture))),
+            ("edit_rgba", peak_memory(|| painting.edit_rgba(for_editor))),
+            ("png", peak_memory(|| encode_png(&img, &PngOptions::default(), std::io::sink()).unwrap())),
+            ("jpeg", peak_memory(|| encode_jpeg(&img, &JpegOptions::default(), std::io::sink()).unwrap())),
+            ("gif", peak_memory(|| encode_gif(&img, &GifOptions::default(), std::io::sink()).unwrap())),
+        ];
+        for (stage, bytes) in stages {
+            println!("peak memory {}/{}: {:.1} MiB", stage, name, bytes as f64 / (1024. * 1024.));
+        }
+    }
+}
+
+fn capture(c: &mut Criterion) {
+    let mut group = c.benchmark_group("capture");
+    group.sample_size(10);
+    for (name, sizes) in SCREENS {
+        let backend = backend(sizes);
+        group.bench_function(BenchmarkId::new("capture_displays", name), |b| b.iter(|| captures(&backend)));
+        group.bench_function(BenchmarkId::new("composite", name), |b| {
+            b.iter_batched(|| captures(&backend), |captures| composite(captures).unwrap(), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn editor(c: &mut Criterion) {
+    let mut group = c.benchmark_group("editor");
+    group.sample_size(10);
+    for (name, sizes) in SCREENS {
+        let img = screenshot(&backend(sizes));
+        group.bench_function(BenchmarkId::new("load_image_from_memory", name), |b| {
+            b.iter_batched(|| img.clone(), load_image_from_memory, BatchSize::LargeInput)
+        });
+        let mut painting = annotated(&img);
+        group.bench_function(BenchmarkId::new("edit_rgba", name), |b| {
+            b.iter_batched(|| img.clone(), |img| painting.edit_rgba(img), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    group.sample_size(10);
+    for (name, sizes) in SCREENS {
+        let img = screenshot(&backend(sizes));
+        group.bench_function(BenchmarkId::new("png", name), |b| {
+            b.iter(|| encode_png(&img, &PngOptions::default(), std::io::sink()).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("jpeg", name), |b| {
+            b.iter(|| encode_jpeg(&img, &JpegOptions::default(), std::io::sink()).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("gif", name), |b| {
+            b.iter(|| encode_gif(&img, &GifOptions::default(), std::io::sink()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, memory, capture, editor, encode);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+//! Screenshot utility: the window (`myapp::MyApp`), the command line (`myapp::clilib`)
+//! and the capture pipeline.
+//!
+//! The library target exists because `benches/` and `tests/` can only link against a lib,
+//! not against the `progetto` binary; `main.rs` is a thin wrapper around it. For the same
+//! reason `impl eframe::App for MyApp` lives in `myapp.rs` next to the type.
+
+pub mod myapp;
@@ -1,10 +1,24 @@
-mod imglib;
+//the pub modules are also used by the benches and the integration tests
+pub mod imglib;
 mod screenlib;
-mod hotkeylib;
+pub mod hotkeylib;
 mod pathlib;
-mod paintlib;
-mod savelib;
+pub mod paintlib;
+pub mod savelib;
 mod cutlib;
+pub mod capturelib;
+pub mod historylib;
+pub mod giflib;
+pub mod exportlib;
+mod templatelib;
+pub mod gallerylib;
+pub mod projectlib;
+pub mod svglib;
+mod pdflib;
+pub mod windowlib;
+pub mod framelib;
+pub mod statelib;
+pub mod clilib;
 
 use std::borrow::Cow;
 use imglib::AllFormats;
@@ -14,21 +28,26 @@ use pathlib::MyPath;
 use paintlib::Painting;
 
 use eframe::emath::Align;
-use eframe::Frame;
-use egui::{Ui, Separator, Context, CentralPanel, TopBottomPanel, Layout, Grid, menu, CollapsingHeader, Window, TextureHandle, Vec2};
+use egui::{Ui, Separator, Context, CentralPanel, TopBottomPanel, Layout, Grid, menu, CollapsingHeader, Window, TextureHandle, Vec2, Align2, Color32, Visuals, Button};
 use std::path::PathBuf;
 use std::env;
 use std::sync::mpsc::{Receiver, Sender};
-use std::time::Duration;
 use arboard::{Clipboard, ImageData};
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager};
 use global_hotkey::hotkey::{Code as KeyCode, HotKey, Modifiers as KeyModifiers};
 use image::{RgbaImage, imageops};
 use serde::{Serialize, Deserialize};
-use crate::myapp::cutlib::MyCut;
-use crate::myapp::hotkeylib::STD_HOTKEYS;
-use crate::myapp::imglib::{load_image_from_memory, restore_dim};
+use crate::myapp::capturelib::{default_backend, CaptureBackend, CaptureInfo};
+use crate::myapp::exportlib::ExportOptions;
+use crate::myapp::framelib::AppFrame;
+use crate::myapp::gallerylib::{CaptureIndex, CaptureRecord, MyGallery};
+use crate::myapp::hotkeylib::{HotKeyRegistry, STD_HOTKEYS};
+use crate::myapp::imglib::load_image_from_memory;
 use crate::myapp::paintlib::Shapes;
-use crate::myapp::savelib::MySave;
+use crate::myapp::savelib::{MySave, SaveError, SaveResult};
+use crate::myapp::projectlib::{project_data, save_project_tokio};
+use crate::myapp::statelib::{AppEvent, AppState, Mode, Overlay};
+use crate::myapp::templatelib::{render_template, TemplateValues, DEFAULT_TEMPLATE};
 
 pub const PADDING: f32 = 5.0;
 
@@ -38,6 +57,65 @@ pub struct Config {
     pub save_screenshot: (u32, String, String),
     pub format: String,
     pub path: PathBuf,
+    #[serde(default = "default_template")]
+    pub name_template: String,
+    /// value of the next {counter} token
+    #[serde(default)]
+    pub counter: u32,
+    /// display captured by the overlay and the cli (1 = first), all of them when missing
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    //tables last, toml wants plain values first
+    #[serde(default)]
+    pub export: ExportOptions,
+    /// file the settings are loaded from and stored to, the capture index is next to it
+    #[serde(skip)]
+    pub file: PathBuf,
+}
+
+fn default_template() -> String {
+    DEFAULT_TEMPLATE.to_string()
+}
+
+impl Config {
+    /** il file di confy, usato quando non ne viene scelto un altro **/
+    pub fn default_file() -> PathBuf {
+        confy::get_configuration_file_path("screenshot", "screenshot").unwrap_or_else(|_| PathBuf::from("screenshot.toml"))
+    }
+
+    /** impostazioni salvate in `file`, quelle predefinite se mancano o non sono leggibili **/
+    pub fn load(file: PathBuf) -> Self {
+        let config: Config = confy::load_path(&file).unwrap_or_default();
+        Self { file, ..config }
+    }
+
+    pub fn store(&self) -> Result<(), confy::ConfyError> {
+        confy::store_path(&self.file, self)
+    }
+
+    /** l'indice delle catture, accanto al file delle impostazioni **/
+    pub fn index_path(&self) -> PathBuf {
+        self.file.with_file_name("captures.json")
+    }
+
+    /** nome del prossimo file secondo il modello, senza toccare il contatore: due salvataggi
+    partiti insieme hanno lo stesso nome, `unique_path` prenota il file creandolo e il secondo
+    prende `nome_(1)` **/
+    pub fn next_name(&self, capture: CaptureInfo, width: u32, height: u32) -> String {
+        let values = TemplateValues { now: chrono::Local::now(), capture, width, height, counter: self.counter };
+        render_template(&self.name_template, &values).unwrap_or_else(|e| {
+            eprintln!("Invalid file name template: {}", e);
+            MySave::default_name()
+        })
+    }
+
+    /** dopo un salvataggio riuscito il contatore passa al prossimo valore e viene salvato **/
+    pub fn advance_counter(&mut self) {
+        self.counter += 1;
+        if let Err(e) = self.store() {
+            eprintln!("Unable to save the settings: {}", e);
+        }
+    }
 }
 
 impl Default for Config {
@@ -53,17 +131,34 @@ impl Default for Config {
             save_screenshot: (ids, KeyModifiersWrapper(KeyModifiers::CONTROL).to_string(), KeyCodeWrapper(KeyCode::KeyS).to_string()),
             format: String::from("png"),
             path: env::current_dir().expect("Current directory not accessible"),
+            name_template: default_template(),
+            counter: 0,
+            monitor: None,
+            export: ExportOptions::default(),
+            file: Config::default_file(),
         }
     }
 }
 
-#[derive(PartialEq, Copy, Clone)]
+/// Seconds a successful save stays on screen, errors stay until closed.
+const NOTIFICATION_SECS: f64 = 4.;
+
+/// Outcome of a save shown in the bottom right corner.
+pub struct Notification {
+    pub text: String,
+    pub error: bool,
+    /// `ctx` time when the result arrived
+    pub time: f64,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Layouts{
     Home,
     Hotkey,
     Screenshot,
     Path,
-    About
+    About,
+    History,
 }
 
 pub struct MyApp {
@@ -72,73 +167,137 @@ pub struct MyApp {
     screen_ly: MyScreenshot,
     pub hotkey_ly: MyHotKey,
     path_ly: MyPath,
-    pub layout: Layouts,
-    pub open_settings: bool,
-    pub open_save: bool,
+    /// loaded again every time the history is opened
+    gallery_ly: Option<MyGallery>,
+    pub state: AppState,
+    pub open_history: bool,
     img: Option<RgbaImage>,
+    /// how the current image was captured
+    capture: CaptureInfo,
     //a prova potremmo cambiare nome
     prova: Option<RgbaImage>,
     pub texture: Option<TextureHandle>,
     painting: Option<Painting>,
+    /// annotations of the project being opened, used instead of a new `Painting`
+    opened_painting: Option<Painting>,
     save_ly: Option<MySave>,
     clipboard: Option<Clipboard>,
     wait: bool,
     timeout: f64,
-    pub(crate) disabled_time: f64,
-    pub saving: bool,
     //usati per tokio
-    pub tx: Sender<Duration>,
-    pub rx: Receiver<Duration>,
+    pub tx: Sender<SaveResult>,
+    pub rx: Receiver<SaveResult>,
+    pub notification: Option<Notification>,
     shape: Shapes,
-    mycut: Option<MyCut>,
-    copy: bool,
-    pub save_by_hk: bool,
-    pub dim: Option<(f32, f32)>,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
-        let config : Config = confy::load("screenshot", "screenshot").unwrap_or_default();
-        //println!("{:?}", confy::get_configuration_file_path("screenshot", "screenshot").unwrap());
+        Self::from_config(Config::load(Config::default_file()))
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.show(ctx, frame);
+    }
+
+    fn clear_color(&self, _visuals: &Visuals) -> [f32; 4] {
+        //Color32::from_rgba_unmultiplied(12, 12, 12, 180).to_normalized_gamma_f32()
+        Color32::from_rgba_unmultiplied(0, 0, 0, 128).to_normalized_gamma_f32()
+    }
+}
+
+impl MyApp {
+    /** l'applicazione sugli schermi veri con le impostazioni date **/
+    pub fn from_config(config: Config) -> Self {
+        let hotkeys = GlobalHotKeyManager::new().expect("Unable to create the hotkey manager");
+        Self::new(config, default_backend(), Box::new(hotkeys), Clipboard::new().ok())
+    }
+
+    /** l'applicazione con i servizi del sistema passati da fuori, i test headless ne usano di finti **/
+    pub fn new(config: Config, backend: Box<dyn CaptureBackend>, hotkeys: Box<dyn HotKeyRegistry>,
+               clipboard: Option<Clipboard>) -> Self {
         let paths = config.path.clone();
         let format = imglib::format_from_string(config.format.as_str()).unwrap_or(AllFormats::PNG);
         let take_screenshot = config.take_screenshot.clone();
         let save_screenshot = config.save_screenshot.clone();
-        let clipboard = Clipboard::new().ok();
         let (tx, rx) = std::sync::mpsc::channel();
+        let monitor = config.monitor;
         Self {
             config,
             format,
-            screen_ly: MyScreenshot::default(),
-            hotkey_ly: MyHotKey::new(take_screenshot, save_screenshot),
+            screen_ly: MyScreenshot::new(backend, monitor),
+            hotkey_ly: MyHotKey::new(hotkeys, take_screenshot, save_screenshot),
             path_ly: MyPath::new(paths.clone()),
-            layout: Layouts::Home,
-            open_settings: false,
-            open_save: false,
+            gallery_ly: None,
+            state: AppState::default(),
+            open_history: false,
             img: None,
+            capture: CaptureInfo::default(),
             prova: None,
             texture: None,
             painting: None,
+            opened_painting: None,
             save_ly: None,
-            saving: false,
             clipboard,
             tx,
             rx,
+            notification: None,
             wait: false,
             timeout: 0.,
-            disabled_time: f64::NEG_INFINITY,
             shape: Shapes::None,
-            mycut: None,
-            copy: false,
-            save_by_hk: false,
-            dim: None,
         }
     }
-}
 
-impl MyApp {
+    /** un frame dell'applicazione, senza dipendere dalla finestra nativa **/
+    pub fn show(&mut self, ctx: &Context, _frame: &mut dyn AppFrame) {
+        ctx.set_visuals(Visuals::dark());
+        ctx.request_repaint();
+
+        //global hotkey event receiver
+        if let Ok(event) = GlobalHotKeyEvent::receiver().try_recv() {
+            //println!("{:?}", event);
+            if self.hotkey_ly.match_event(event, _frame, &mut self.state, ctx.input(|i| i.time), &mut self.config) {
+                self.save_by_hotkey(ctx);
+            }
+        }
+
+        //save result receiver
+        if let Ok(result) = self.rx.try_recv() {
+            //a finished report says nothing about the save of the editor, which may still be running
+            if !result.as_ref().is_ok_and(|saved| saved.report) {
+                self.state.handle(AppEvent::SaveFinished, _frame);
+            }
+            self.notify_save(result, ctx.input(|i| i.time));
+        }
+
+        //matcher for layout navigation
+        match self.state.layout() {
+            Layouts::Home => {
+                self.home_layout(ctx,_frame);
+            },
+            Layouts::Screenshot => {
+                self.screen_layout(ctx, _frame);
+            },
+            Layouts::Hotkey => {
+                self.hotkey_layout(ctx, _frame);
+            },
+            Layouts::Path => {
+                self.path_layout(ctx, _frame);
+            },
+            Layouts::About => {
+                self.about_layout(ctx, _frame);
+            },
+            Layouts::History => {
+                self.gallery_layout(ctx, _frame);
+            },
+        }
+        self.render_notification(ctx);
+    }
+
     /**schermata home**/
-    pub fn home_layout(&mut self, ctx: &Context, _frame: &mut Frame){
+    pub fn home_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame){
         CentralPanel::default().show(ctx, |ui| {
             menu::bar(ui, |ui|{
                 ui.horizontal(|ui|{
@@ -146,10 +305,7 @@ impl MyApp {
                         if ui.button("+ New")
                             .on_hover_text(format!("{} + {}", self.config.take_screenshot.1, self.config.take_screenshot.2))
                             .clicked() {
-                            _frame.set_visible(false);
-                            self.open_settings = false;
-                            self.disabled_time = ui.input(|i| i.time);
-                            self.layout = Layouts::Screenshot;
+                            self.state.handle(AppEvent::NewCapture { time: ui.input(|i| i.time) }, _frame);
                         }
                     });
                     egui::ComboBox::from_label("🕒")
@@ -174,15 +330,18 @@ impl MyApp {
 
                     ui.with_layout(Layout::right_to_left(Align::TOP), |ui|{
                         self.render_settings(ui, _frame);
+                        if ui.button("🖼").on_hover_text("Captures").clicked() && self.state.handle(AppEvent::Open(Layouts::History), _frame) {
+                            self.gallery_ly = Some(MyGallery::new(self.config.index_path(), self.config.path.clone(), self.config.export.pdf.clone(), self.tx.clone()));
+                        }
                     });
                 });
             });
 
             ui.horizontal_centered(|ui|{
-                self.render_body(ui);
+                self.render_body(ui, _frame);
             });
 
-            if self.texture.is_some() && self.mycut.is_none() {
+            if self.texture.is_some() && self.state.cut().is_none() {
                 ui.horizontal_wrapped(|ui| {
                     let painting = self.painting.as_mut().unwrap();
                     painting.stroke(ui);
@@ -201,20 +360,67 @@ impl MyApp {
                                 self.shape = Shapes::Circle;
                                 painting.set_shape(Shapes::Circle);
                             }
+                            let arrow = Shapes::Arrow(painting.arrow_head());
+                            if ui.selectable_value(&mut self.shape, arrow.clone(), arrow.to_name()).clicked() {
+                                painting.set_shape(arrow);
+                            }
+                            let text = Shapes::Text(painting.text_box());
+                            if ui.selectable_value(&mut self.shape, text.clone(), text.to_name()).clicked() {
+                                painting.set_shape(text);
+                            }
+                            let redact = Shapes::Redact(painting.redaction());
+                            if ui.selectable_value(&mut self.shape, redact.clone(), redact.to_name()).clicked() {
+                                painting.set_shape(redact);
+                            }
+                            if ui.selectable_value(&mut self.shape, Shapes::Select, Shapes::Select.to_name()).clicked() {
+                                painting.set_shape(Shapes::Select);
+                            }
                         });
+                    if let Shapes::Arrow(_) = self.shape {
+                        painting.arrow_options(ui);
+                        self.shape = Shapes::Arrow(painting.arrow_head());
+                    }
+                    if let Shapes::Text(_) = self.shape {
+                        painting.text_options(ui);
+                        self.shape = Shapes::Text(painting.text_box());
+                    }
+                    if let Shapes::Redact(_) = self.shape {
+                        painting.redaction_options(ui);
+                        self.shape = Shapes::Redact(painting.redaction());
+                    }
+                    if let Shapes::Select = self.shape {
+                        painting.selection_options(ui);
+                    }
                     if ui.button("✂")
                         .on_hover_text(format!("{} + {}", STD_HOTKEYS[4].0.to_string(), STD_HOTKEYS[4].1.to_string()))
                         .clicked() {
-                        self.mycut = Some(MyCut::default());
+                        self.state.handle(AppEvent::OpenCut, _frame);
                     }
                     if ui.button("💾")
                         .on_hover_text(format!("{} + {}", self.config.save_screenshot.1, self.config.save_screenshot.2))
-                        .clicked() {
-                        self.open_save = true;
+                        .clicked() && self.state.handle(AppEvent::OpenSave, _frame) {
                         let rgba = painting.edit_rgba(self.prova.clone().unwrap());
+                        let (_, w, h) = rgba.as_ref().unwrap();
                         self.save_ly = Some(MySave::new(self.config.path.clone(),
                                                         imglib::format_from_string(self.config.format.as_str()).unwrap_or(AllFormats::PNG),
-                                                        rgba.as_ref().unwrap(), self.tx.clone()));
+                                                        self.config.export.clone(),
+                                                        self.config.next_name(self.capture, *w, *h),
+                                                        rgba.as_ref().unwrap(),
+                                                        Some(painting.vector_image(self.prova.clone().unwrap())),
+                                                        self.tx.clone()));
+                    }
+                    if ui.button("🗐").on_hover_text("Save as project, annotations stay editable").clicked() {
+                        let image = self.prova.clone().unwrap();
+                        //a failure is a result too, it ends the save like the others
+                        self.state.handle(AppEvent::SaveStarted, _frame);
+                        match project_data(painting, self.capture) {
+                            Ok(data) => {
+                                let name = self.config.next_name(self.capture, image.width(), image.height());
+                                save_project_tokio(image, data, self.config.path.clone(), name,
+                                                   self.tx.clone(), ui.ctx().clone());
+                            }
+                            Err(e) => { let _ = self.tx.send(Err(SaveError::Io(e.into()))); }
+                        }
                     }
                     ui.separator();
                     if ui.button("↩")
@@ -232,68 +438,70 @@ impl MyApp {
                         .clicked() {
                         painting.clear();
                     }
-                    if self.save_by_hk {
-                        let rgba = painting.edit_rgba(self.prova.clone().unwrap());
-                        MySave::save_image_tokio(rgba,
-                                                 self.config.path.clone(),
-                                                 "".to_string(),
-                                                 self.format.clone(),
-                                                 self.tx.clone(),
-                                                 ui.ctx().clone());
-                        self.save_by_hk = false;
-                        self.saving = true;
+                    if ui.selectable_label(self.open_history, "📜").on_hover_text("History").clicked() {
+                        self.open_history = !self.open_history;
                     }
-                    if self.saving {
+                    Window::new("HISTORY")
+                        .open(&mut self.open_history)
+                        .show(ui.ctx(), |ui| painting.history_ui(ui));
+                    if self.state.is_saving() {
                         ui.spinner();
                         ui.label("Saving 😺 ...");
                     }
-                    if self.copy {
-                        let rgba = painting.edit_rgba(self.prova.clone().unwrap());
-                        let img = rgba.as_ref().unwrap();
-                        let img_data =  ImageData {
-                            width: img.1 as usize,
-                            height: img.2 as usize,
-                            bytes: Cow::from(img.0.to_vec()),
-                        };
-                        if let Some(clip) = self.clipboard.as_mut() {
-                            clip.set_image(img_data.to_owned_img()).unwrap_or(println!("Error in cpy on clipboard"));
-                            println!("Image copied on clipboard");
-                        }
-                        self.copy = false;
-                    }
                 });
-                self.hotkey_ly.edit_hotkeys(ui, self.painting.as_mut().unwrap(), &mut self.copy, &mut self.mycut);
+                if self.hotkey_ly.edit_hotkeys(ui, self.painting.as_mut().unwrap(), &mut self.state, _frame) {
+                    self.copy_to_clipboard();
+                }
+                //undo/redo of a cut brings back the other image, annotations are already restored
+                if let Some(image) = self.painting.as_mut().unwrap().take_restored_image() {
+                    self.texture = Some(ui.ctx().load_texture(
+                        "my-image",
+                        load_image_from_memory(image.clone()),
+                        Default::default(),
+                    ));
+                    self.prova = Some(image);
+                }
             }
-            else if self.mycut.is_some() {
+            else if self.state.cut().is_some() {
 
                 ui.horizontal(|ui| {
                     if ui.button("✔").clicked() {
-                        let cutrect = self.mycut.clone().unwrap().get_cut_rect(Vec2::new(self.prova.clone().unwrap().width() as f32, self.prova.clone().unwrap().height() as f32));
-                        self.img = Some(imageops::crop(&mut self.prova.clone().unwrap(),
-                                                       cutrect.min.x.round() as u32,
-                                                       cutrect.min.y.round() as u32,
-                                                       cutrect.size().x.round() as u32,
-                                                       cutrect.size().y.round() as u32)
-                            .to_image());
+                        let mycut = self.state.cut_mut().unwrap();
+                        let cutrect = mycut.get_cut_rect(Vec2::new(self.prova.clone().unwrap().width() as f32, self.prova.clone().unwrap().height() as f32));
+                        let cropped = imageops::crop(&mut self.prova.clone().unwrap(),
+                                                     cutrect.min.x.round() as u32,
+                                                     cutrect.min.y.round() as u32,
+                                                     cutrect.size().x.round() as u32,
+                                                     cutrect.size().y.round() as u32)
+                            .to_image();
+                        let rect = mycut.get_rect();
                         if self.painting.is_some() {
-                            self.painting.as_mut().unwrap().adapt_to_cut(self.mycut.clone().unwrap().get_rect());
+                            self.painting.as_mut().unwrap().adapt_to_cut(rect, self.prova.clone().unwrap(), cropped.clone());
                         }
-
+                        //the painting stays, only the image changes
+                        self.texture = Some(ui.ctx().load_texture(
+                            "my-image",
+                            load_image_from_memory(cropped.clone()),
+                            Default::default(),
+                        ));
+                        self.prova = Some(cropped);
+                        self.state.handle(AppEvent::CloseCut, _frame);
                     } else if ui.button("✖").clicked() {
-                        self.mycut = None;
+                        self.state.handle(AppEvent::CloseCut, _frame);
                     }
                 });
 
             }
-            self.render_save(ui);
+            self.render_save(ui, _frame);
         });
     }
 
-    pub fn render_settings(&mut self, ui: &mut Ui, frame: &mut Frame) {
-        if ui.button("⚙").clicked() {
-            self.open_settings = !self.open_settings;
+    pub fn render_settings(&mut self, ui: &mut Ui, frame: &mut dyn AppFrame) {
+        //no settings while cutting or saving
+        if ui.add_enabled(self.state.allows(AppEvent::ToggleSettings), Button::new("⚙")).clicked() {
+            self.state.handle(AppEvent::ToggleSettings, frame);
         }
-        if self.open_settings {
+        if self.state.mode() == Mode::Home(Overlay::Settings) {
             Window::new("SETTINGS")
                 .show(ui.ctx(), |ui| {
                     Grid::new("settings_grid")
@@ -308,10 +516,7 @@ impl MyApp {
                                     ui.label(format!("Save Screenshot: {} + {}", self.config.save_screenshot.1, self.config.save_screenshot.2));
                                 });
                                 if ui.button("Change HotKey").clicked() {
-                                    self.dim = Some((frame.info().window_info.size.x, frame.info().window_info.size.y));
-                                    self.open_settings = false;
-                                    restore_dim(&None, frame, Some(Layouts::Hotkey));
-                                    self.layout = Layouts::Hotkey;
+                                    self.state.handle(AppEvent::Open(Layouts::Hotkey), frame);
                                 }
                             });
                             ui.end_row();
@@ -322,20 +527,47 @@ impl MyApp {
                                 .show_ui(ui, |ui| {
                                     ui.style_mut().wrap = Some(false);
                                     ui.set_min_width(60.0);
-                                    if ui.selectable_value(&mut self.format, AllFormats::PNG, "PNG").clicked() {
-                                        self.config.format = "png".to_string();
-                                        confy::store("screenshot", "screenshot", &self.config).unwrap();
-                                    };
-                                    if ui.selectable_value(&mut self.format, AllFormats::JPEG, "JPEG").clicked() {
-                                        self.config.format = "jpeg".to_string();
-                                        confy::store("screenshot", "screenshot", &self.config).unwrap();
-                                    };
-                                    if ui.selectable_value(&mut self.format, AllFormats::GIF, "GIF").clicked() {
-                                        self.config.format = "gif".to_string();
-                                        confy::store("screenshot", "screenshot", &self.config).unwrap();
-                                    };
+                                    for format in AllFormats::all() {
+                                        let label = format!("{:?}", format);
+                                        if ui.selectable_value(&mut self.format, format, label).clicked() {
+                                            self.config.format = self.format.to_string();
+                                            self.store_config(ui.input(|i| i.time));
+                                        };
+                                    }
                                 });
                             ui.end_row();
+                            ui.label("Format Options");
+                            ui.menu_button("Options", |ui| {
+                                let before = self.config.export.clone();
+                                for format in [AllFormats::JPEG, AllFormats::PNG, AllFormats::GIF, AllFormats::PDF] {
+                                    CollapsingHeader::new(format!("{:?}", format)).show(ui, |ui| {
+                                        self.config.export.options_ui(ui, &format);
+                                    });
+                                }
+                                if self.config.export != before {
+                                    self.store_config(ui.input(|i| i.time));
+                                }
+                            });
+                            ui.end_row();
+                            ui.label("File Name");
+                            ui.vertical(|ui| {
+                                if ui.text_edit_singleline(&mut self.config.name_template).changed() {
+                                    self.store_config(ui.input(|i| i.time));
+                                }
+                                let (width, height) = self.prova.as_ref().map(|p| p.dimensions()).unwrap_or((1920, 1080));
+                                let values = TemplateValues {
+                                    now: chrono::Local::now(),
+                                    capture: self.capture,
+                                    width,
+                                    height,
+                                    counter: self.config.counter,
+                                };
+                                match render_template(&self.config.name_template, &values) {
+                                    Ok(name) => ui.weak(format!("{}.{}", name, self.format.to_string())),
+                                    Err(e) => ui.colored_label(egui::Color32::RED, e),
+                                };
+                            });
+                            ui.end_row();
                             ui.label("Default Path");
                             ui.menu_button("Path", |ui| {
                                 CollapsingHeader::new("Default Path").show(ui, |ui| {
@@ -343,22 +575,16 @@ impl MyApp {
                                     ui.label(path);
                                 });
                                 if ui.button("Change Path").clicked() {
-                                    self.open_settings = false;
-                                    self.dim = Some((frame.info().window_info.size.x, frame.info().window_info.size.y));
-                                    restore_dim(&None, frame, Some(Layouts::Path));
-                                    self.layout = Layouts::Path;
+                                    self.state.handle(AppEvent::Open(Layouts::Path), frame);
                                 }
                             });
                             ui.end_row();
                             if ui.button("About").clicked() {
-                                self.open_settings = false;
-                                self.dim = Some((frame.info().window_info.size.x, frame.info().window_info.size.y));
-                                restore_dim(&None, frame, Some(Layouts::About));
-                                self.layout = Layouts::About;
+                                self.state.handle(AppEvent::Open(Layouts::About), frame);
                             }
                             ui.with_layout(Layout::right_to_left(Align::RIGHT), |ui|{
                                 if ui.button("↩").clicked() {
-                                    self.open_settings = false;
+                                    self.state.handle(AppEvent::CloseSettings, frame);
                                 }
                             });
                         });
@@ -366,24 +592,21 @@ impl MyApp {
         }
     }
 
-    pub fn render_save(&mut self, ui: &mut Ui){
-        if self.open_save {
+    pub fn render_save(&mut self, ui: &mut Ui, frame: &mut dyn AppFrame){
+        if self.state.mode() == Mode::Home(Overlay::Save) {
             Window::new("SAVE TO FILE").show(ui.ctx(), |ui| {
                 if let Some(save_ly) = &mut self.save_ly {
-                    save_ly.save_body(ui, &mut self.open_save, &mut self.saving);
+                    save_ly.save_body(ui, &mut self.state, frame);
                 }
             });
         }
     }
 
-    pub fn render_body(&mut self, ui: &mut Ui) {
+    pub fn render_body(&mut self, ui: &mut Ui, frame: &mut dyn AppFrame) {
         if let Some(buff) = self.img.take() {
             self.prova = Some(buff.clone());
-            if self.mycut.is_none() {
-                self.painting = Some(Painting::default());
-            } else {
-                self.mycut = None;
-            }
+            self.painting = Some(self.opened_painting.take().unwrap_or_default());
+            self.state.handle(AppEvent::ImageLoaded, frame);
             //renderizza immagine + tast
             //self.clicked = Some(ButtonClicked::Paint);
             //render_top_panel(ctx, _frame);
@@ -407,7 +630,7 @@ impl MyApp {
                     egui::Frame::canvas(ui.style())
                         .show(ui, |ui| {
                             if let Some(texture) = self.texture.as_ref() {
-                                painting.ui_content(ui, &texture, &mut self.mycut);
+                                painting.ui_content(ui, &texture, self.state.cut_mut());
                             }
                         });
                 });
@@ -415,29 +638,145 @@ impl MyApp {
         }
     }
 
-    pub fn screen_layout(&mut self, ctx: &Context, _frame: &mut Frame) {
-        let enabled = ctx.input(|i| i.time) - self.disabled_time > self.timeout;
+    pub fn screen_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame) {
+        let enabled = ctx.input(|i| i.time) - self.state.capture_time() > self.timeout;
         if enabled {
             _frame.set_visible(true);
             self.wait = false;
-            self.screen_ly.screen_layout(ctx, _frame, &mut self.layout, &mut self.img,
-                                         &mut self.clipboard);
+            self.screen_ly.screen_layout(ctx, _frame, &mut self.state, &mut self.img,
+                                         &mut self.capture, &mut self.clipboard);
+            if self.screen_ly.monitor != self.config.monitor {
+                self.config.monitor = self.screen_ly.monitor;
+                self.store_config(ctx.input(|i| i.time));
+            }
         }
     }
 
-    pub fn hotkey_layout(&mut self, ctx: &Context, _frame: &mut Frame){
+    /** salva le impostazioni, se non si puó (es. `--config` non scrivibile) lo dice con una notifica **/
+    fn store_config(&mut self, time: f64) {
+        if let Err(e) = self.config.store() {
+            let text = format!("Unable to save the settings: {}", e);
+            eprintln!("{}", text);
+            self.notification = Some(Notification { text, error: true, time });
+        }
+    }
+
+    /** trasforma l'esito del salvataggio in una notifica **/
+    pub fn notify_save(&mut self, result: SaveResult, time: f64) {
+        let (text, error) = match result {
+            Ok(saved) if saved.report => {
+                (format!("Report saved {} ({}) in {:.2?}", saved.path.display(), format_size(saved.size), saved.duration), false)
+            }
+            Ok(saved) => {
+                //only a file on disk uses up a {counter}, an abandoned save window does not
+                self.config.advance_counter();
+                let record = CaptureRecord::new(saved.path.clone(), saved.width, saved.height, self.capture);
+                if let Err(e) = CaptureIndex::append(&self.config.index_path(), record) {
+                    eprintln!("Unable to update the capture history: {}", e);
+                }
+                (format!("Saved {} ({}) in {:.2?}", saved.path.display(), format_size(saved.size), saved.duration), false)
+            }
+            Err(e) => (format!("Save failed: {}", e), true),
+        };
+        if error {
+            eprintln!("{}", text);
+        }
+        self.notification = Some(Notification { text, error, time });
+    }
+
+    /** salva con nome e formato di default, quando la hotkey é stata accettata **/
+    fn save_by_hotkey(&mut self, ctx: &Context) {
+        let (Some(painting), Some(image)) = (self.painting.as_mut(), self.prova.clone()) else { return };
+        let rgba = painting.edit_rgba(image.clone());
+        let (_, w, h) = rgba.as_ref().unwrap();
+        let name = self.config.next_name(self.capture, *w, *h);
+        if self.format == AllFormats::SVG {
+            MySave::save_vector_tokio(painting.vector_image(image),
+                                      self.config.path.clone(),
+                                      name,
+                                      self.tx.clone(),
+                                      ctx.clone());
+        } else {
+            MySave::save_image_tokio(rgba,
+                                     self.config.path.clone(),
+                                     name,
+                                     self.format.clone(),
+                                     self.config.export.clone(),
+                                     self.tx.clone(),
+                                     ctx.clone());
+        }
+    }
+
+    /** copia negli appunti l'immagine con le annotazioni **/
+    fn copy_to_clipboard(&mut self) {
+        let (Some(painting), Some(image)) = (self.painting.as_mut(), self.prova.clone()) else { return };
+        let rgba = painting.edit_rgba(image);
+        let img = rgba.as_ref().unwrap();
+        let img_data =  ImageData {
+            width: img.1 as usize,
+            height: img.2 as usize,
+            bytes: Cow::from(img.0.to_vec()),
+        };
+        if let Some(clip) = self.clipboard.as_mut() {
+            clip.set_image(img_data.to_owned_img()).unwrap_or(println!("Error in cpy on clipboard"));
+            println!("Image copied on clipboard");
+        }
+    }
+
+    pub fn render_notification(&mut self, ctx: &Context) {
+        //the screenshot layout is a transparent fullscreen window
+        if self.state.layout() == Layouts::Screenshot {
+            return;
+        }
+        let Some(notification) = &self.notification else { return };
+        if !notification.error && ctx.input(|i| i.time) - notification.time > NOTIFICATION_SECS {
+            self.notification = None;
+            return;
+        }
+        let mut close = false;
+        Window::new("notification")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if notification.error {
+                        ui.colored_label(Color32::LIGHT_RED, "⚠");
+                    } else {
+                        ui.colored_label(Color32::LIGHT_GREEN, "✔");
+                    }
+                    ui.label(&notification.text);
+                    close = ui.button("✖").clicked();
+                });
+            });
+        if close {
+            self.notification = None;
+        }
+    }
+
+    pub fn hotkey_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame){
         self.hotkey_ly.hotkey_layout(ctx,
                                      _frame,
                                      &mut self.config,
-                                     &mut self.layout,
-                                     &self.dim);
+                                     &mut self.state);
+    }
+
+    pub fn path_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame){
+        self.path_ly.path_layout(ctx, _frame, &mut self.state, &mut self.config);
     }
 
-    pub fn path_layout(&mut self, ctx: &Context, _frame: &mut Frame){
-        self.path_ly.path_layout(ctx, _frame, &mut self.layout, &mut self.config, &self.dim);
+    pub fn gallery_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame) {
+        let gallery = self.gallery_ly.get_or_insert_with(|| {
+            MyGallery::new(self.config.index_path(), self.config.path.clone(), self.config.export.pdf.clone(), self.tx.clone())
+        });
+        if let Some((img, capture, painting)) = gallery.gallery_layout(ctx, _frame, &mut self.state, &mut self.clipboard) {
+            self.img = Some(img);
+            self.capture = capture;
+            self.opened_painting = painting;
+        }
     }
 
-    pub fn about_layout(&mut self, ctx: &Context, _frame: &mut Frame){
+    pub fn about_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame){
         CentralPanel::default().show(ctx, |ui| {
             render_header(ui, "ABOUT");
             ui.vertical_centered(|ui|{
@@ -454,16 +793,23 @@ impl MyApp {
             render_footer(ctx);
             ui.with_layout(Layout::right_to_left(Align::RIGHT), |ui|{
                 if ui.button("↩").clicked() {
-                    restore_dim(&self.dim, _frame, Some(Layouts::Home));
-                    self.layout = Layouts::Home;
+                    self.state.handle(AppEvent::Back, _frame);
                 }
             });
         });
     }
 }
 
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KB", bytes as f64 / 1024.),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.),
+    }
+}
+
 /*
-pub fn render_top_panel(ctx :&Context, frame: &mut Frame){
+pub fn render_top_panel(ctx :&Context, frame: &mut dyn AppFrame){
     TopBottomPanel::top("top_panel").show(ctx, |ui|{
         //ui.add_space(10.);
         menu::bar(ui, |ui|{
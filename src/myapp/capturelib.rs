@@ -0,0 +1,407 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use image::RgbaImage;
+use screenshots::Screen;
+use serde::{Serialize, Deserialize};
+
+/// Geometry of a connected display as the backend reports it: position and size are in logical
+/// pixels of the virtual desktop, the captured image is `scale_factor` times larger.
+/// `physical_layout` gives where each display is in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Monitor {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+impl Monitor {
+    /** i display non hanno un nome, vengono numerati come nella cli (1 = primo) **/
+    pub fn label(&self, number: usize) -> String {
+        let primary = if self.is_primary { ", primary" } else { "" };
+        format!("Display {}: {}×{} at {}%{}", number, self.width, self.height, (self.scale_factor * 100.).round(), primary)
+    }
+}
+
+/// A rectangle of the virtual desktop in physical pixels, as X11 reports window positions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ScreenRect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width as i32 && y >= self.y && y < self.y + self.height as i32
+    }
+
+    pub fn intersect(&self, other: &ScreenRect) -> Option<ScreenRect> {
+        let x1 = self.x.max(other.x);
+        let y1 = self.y.max(other.y);
+        let x2 = (self.x + self.width as i32).min(other.x + other.width as i32);
+        let y2 = (self.y + self.height as i32).min(other.y + other.height as i32);
+        if x1 >= x2 || y1 >= y2 {
+            return None;
+        }
+        Some(ScreenRect { x: x1, y: y1, width: (x2 - x1) as u32, height: (y2 - y1) as u32 })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum CaptureMode {
+    #[default]
+    Full,
+    Region,
+    Window,
+}
+
+impl CaptureMode {
+    pub fn to_name(self) -> &'static str {
+        match self {
+            CaptureMode::Full => "full",
+            CaptureMode::Region => "region",
+            CaptureMode::Window => "window",
+        }
+    }
+}
+
+/// What was captured, used to name the saved file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct CaptureInfo {
+    pub mode: CaptureMode,
+    /// 1 = first display, `None` when the capture spans several displays
+    pub monitor: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    NoDisplays,
+    DisplayNotFound(u32),
+    InvalidArea,
+    Backend(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::NoDisplays => write!(f, "no display available"),
+            CaptureError::DisplayNotFound(id) => write!(f, "display {} not found", id),
+            CaptureError::InvalidArea => write!(f, "area size is invalid"),
+            CaptureError::Backend(e) => write!(f, "capture backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+/// Source of screen pixels. `capture_area` takes logical coordinates relative to the display
+/// and, like `capture_display`, returns physical pixels.
+/// Displays are captured from several threads at once.
+pub trait CaptureBackend: Sync {
+    fn displays(&self) -> Result<Vec<Monitor>, CaptureError>;
+    fn capture_display(&self, monitor: &Monitor) -> Result<RgbaImage, CaptureError>;
+    fn capture_area(&self, monitor: &Monitor, x: i32, y: i32, width: u32, height: u32) -> Result<RgbaImage, CaptureError>;
+}
+
+/// Picks the backend for this run: `PROGETTO_CAPTURE_DIR` points to a folder of
+/// PNG files used as fake displays, otherwise the real screens are grabbed.
+/// It is a documented option of release builds as well, for scripts and machines without a screen.
+pub fn default_backend() -> Box<dyn CaptureBackend> {
+    if let Ok(dir) = std::env::var("PROGETTO_CAPTURE_DIR") {
+        match FakeBackend::from_dir(Path::new(&dir)) {
+            Ok(fake) => return Box::new(fake),
+            Err(e) => eprintln!("Unable to load fake displays from {}: {}", dir, e),
+        }
+    }
+    Box::new(ScreenshotsBackend)
+}
+
+pub struct ScreenshotsBackend;
+
+impl ScreenshotsBackend {
+    fn screen(monitor: &Monitor) -> Result<Screen, CaptureError> {
+        Screen::all()
+            .map_err(|e| CaptureError::Backend(e.to_string()))?
+            .into_iter()
+            .find(|s| s.display_info.id == monitor.id)
+            .ok_or(CaptureError::DisplayNotFound(monitor.id))
+    }
+
+    /** il buffer di screenshots é giá rgba, viene solo spostato **/
+    pub fn to_rgba(image: screenshots::Image) -> Result<RgbaImage, CaptureError> {
+        let (width, height) = (image.width(), image.height());
+        RgbaImage::from_raw(width, height, image.into())
+            .ok_or(CaptureError::Backend(format!("buffer too small for a {}x{} capture", width, height)))
+    }
+}
+
+impl CaptureBackend for ScreenshotsBackend {
+    fn displays(&self) -> Result<Vec<Monitor>, CaptureError> {
+        let screens = Screen::all().map_err(|e| CaptureError::Backend(e.to_string()))?;
+        Ok(screens.iter().map(|s| Monitor {
+            id: s.display_info.id,
+            x: s.display_info.x,
+            y: s.display_info.y,
+            width: s.display_info.width,
+            height: s.display_info.height,
+            scale_factor: s.display_info.scale_factor,
+            is_primary: s.display_info.is_primary,
+        }).collect())
+    }
+
+    fn capture_display(&self, monitor: &Monitor) -> Result<RgbaImage, CaptureError> {
+        let image = Self::screen(monitor)?
+            .capture()
+            .map_err(|e| CaptureError::Backend(e.to_string()))?;
+        Self::to_rgba(image)
+    }
+
+    fn capture_area(&self, monitor: &Monitor, x: i32, y: i32, width: u32, height: u32) -> Result<RgbaImage, CaptureError> {
+        let image = Self::screen(monitor)?
+            .capture_area(x, y, width, height)
+            .map_err(|e| CaptureError::Backend(e.to_string()))?;
+        Self::to_rgba(image)
+    }
+}
+
+/// In-memory displays, useful on machines without a real screen.
+pub struct FakeBackend {
+    screens: Vec<(Monitor, RgbaImage)>,
+}
+
+impl FakeBackend {
+    pub fn new(screens: Vec<(Monitor, RgbaImage)>) -> Self {
+        Self { screens }
+    }
+
+    /// Loads every PNG of `dir` (sorted by name) as a display, placed left to right.
+    pub fn from_dir(dir: &Path) -> Result<Self, CaptureError> {
+        let mut files = fs::read_dir(dir)
+            .map_err(|e| CaptureError::Backend(e.to_string()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")))
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let images = files
+            .iter()
+            .map(|file| image::open(file).map(|image| image.to_rgba8()).map_err(|e| CaptureError::Backend(e.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        if images.is_empty() {
+            return Err(CaptureError::NoDisplays);
+        }
+        Ok(Self::from_images(images))
+    }
+
+    /// One 1x display per image, placed left to right with the first one primary.
+    pub fn from_images(images: Vec<RgbaImage>) -> Self {
+        let mut screens = Vec::new();
+        let mut x = 0;
+        for (id, image) in images.into_iter().enumerate() {
+            let monitor = Monitor {
+                id: id as u32,
+                x,
+                y: 0,
+                width: image.width(),
+                height: image.height(),
+                scale_factor: 1.,
+                is_primary: id == 0,
+            };
+            x += image.width() as i32;
+            screens.push((monitor, image));
+        }
+        Self::new(screens)
+    }
+
+    fn image(&self, monitor: &Monitor) -> Result<&RgbaImage, CaptureError> {
+        self.screens
+            .iter()
+            .find(|(m, _)| m.id == monitor.id)
+            .map(|(_, img)| img)
+            .ok_or(CaptureError::DisplayNotFound(monitor.id))
+    }
+}
+
+impl CaptureBackend for FakeBackend {
+    fn displays(&self) -> Result<Vec<Monitor>, CaptureError> {
+        Ok(self.screens.iter().map(|(m, _)| *m).collect())
+    }
+
+    fn capture_display(&self, monitor: &Monitor) -> Result<RgbaImage, CaptureError> {
+        Ok(self.image(monitor)?.clone())
+    }
+
+    fn capture_area(&self, monitor: &Monitor, x: i32, y: i32, width: u32, height: u32) -> Result<RgbaImage, CaptureError> {
+        let image = self.image(monitor)?;
+        //the area is logical like in screenshots::Screen::capture_area, the image physical
+        let s = monitor.scale_factor;
+        let (x, y) = ((x as f32 * s) as i32, (y as f32 * s) as i32);
+        let (width, height) = ((width as f32 * s) as u32, (height as f32 * s) as u32);
+        //same clamping rules of screenshots::Screen::capture_area
+        let x1 = x.clamp(0, image.width() as i32);
+        let y1 = y.clamp(0, image.height() as i32);
+        let x2 = (x + width as i32).min(image.width() as i32);
+        let y2 = (y + height as i32).min(image.height() as i32);
+        if x1 >= x2 || y1 >= y2 {
+            return Err(CaptureError::InvalidArea);
+        }
+        Ok(image::imageops::crop_imm(image, x1 as u32, y1 as u32, (x2 - x1) as u32, (y2 - y1) as u32).to_image())
+    }
+}
+
+/** posizione e dimensione in pixel fisici di ogni schermo, nello stesso ordine. La dimensione é quella
+della cattura; l'origine somma i tratti del desktop a sinistra e sopra, ognuno con la scala piú alta
+degli schermi che lo attraversano, cosí schermi vicini con scale diverse restano vicini senza sovrapporsi **/
+pub fn physical_layout(monitors: &[Monitor]) -> Vec<ScreenRect> {
+    let columns: Vec<_> = monitors.iter().map(|m| (m.x, m.width, m.scale_factor)).collect();
+    let rows: Vec<_> = monitors.iter().map(|m| (m.y, m.height, m.scale_factor)).collect();
+    monitors
+        .iter()
+        .map(|m| ScreenRect {
+            x: physical_offset(&columns, m.x),
+            y: physical_offset(&rows, m.y),
+            width: (m.width as f32 * m.scale_factor) as u32,
+            height: (m.height as f32 * m.scale_factor) as u32,
+        })
+        .collect()
+}
+
+/** coordinata fisica di `position` lungo un asse, dati gli intervalli logici (inizio, lunghezza, scala)
+degli schermi: ogni tratto tra due bordi conta con la scala piú alta degli schermi che lo coprono **/
+fn physical_offset(spans: &[(i32, u32, f32)], position: i32) -> i32 {
+    let mut edges: Vec<i32> = spans.iter().flat_map(|&(start, len, _)| [start, start + len as i32]).collect();
+    edges.sort_unstable();
+    edges.dedup();
+    let scale = |from: i32, to: i32| {
+        spans
+            .iter()
+            .filter(|&&(start, len, _)| start <= from && to <= start + len as i32)
+            .map(|&(_, _, s)| s)
+            .fold(None, |max: Option<f32>, s| Some(max.map_or(s, |m| m.max(s))))
+            .unwrap_or(1.)
+    };
+    let Some(&first) = edges.first() else { return position };
+    //the desktop starts where it starts, scaled like the display there (all of X11 shares one scale)
+    let mut physical = first as f32 * edges.get(1).map_or(1., |&next| scale(first, next));
+    for pair in edges.windows(2) {
+        let (from, to) = (pair[0], pair[1].min(position));
+        if from >= to {
+            break;
+        }
+        physical += (to - from) as f32 * scale(from, to);
+    }
+    physical.round() as i32
+}
+
+/** un rettangolo in pixel logici del desktop, come la selezione sull'overlay, in pixel fisici: ogni angolo
+passa per la scala e la posizione fisica dello schermo su cui sta (o del piú vicino, se é fuori) **/
+pub fn physical_rect(monitors: &[Monitor], x: f32, y: f32, width: f32, height: f32) -> ScreenRect {
+    let layout = physical_layout(monitors);
+    let corner = |x: f32, y: f32| -> (i32, i32) {
+        let distance = |m: &Monitor| {
+            let dx = (m.x as f32 - x).max(x - (m.x + m.width as i32) as f32).max(0.);
+            let dy = (m.y as f32 - y).max(y - (m.y + m.height as i32) as f32).max(0.);
+            dx + dy
+        };
+        let Some((m, physical)) = monitors.iter().zip(&layout).min_by(|a, b| distance(a.0).total_cmp(&distance(b.0))) else {
+            return (x.round() as i32, y.round() as i32);
+        };
+        (physical.x + ((x - m.x as f32) * m.scale_factor).round() as i32,
+         physical.y + ((y - m.y as f32) * m.scale_factor).round() as i32)
+    };
+    let (x0, y0) = corner(x, y);
+    let (x1, y1) = corner(x + width, y + height);
+    ScreenRect { x: x0, y: y0, width: (x1 - x0).max(0) as u32, height: (y1 - y0).max(0) as u32 }
+}
+
+/** cattura un rettangolo del desktop in pixel fisici anche a cavallo di piú schermi, ritorna anche il numero
+dello schermo (1 = primo) se il rettangolo ne tocca uno solo **/
+pub fn capture_rect(backend: &dyn CaptureBackend, rect: ScreenRect) -> Result<(RgbaImage, Option<usize>), CaptureError> {
+    let displays = backend.displays()?;
+    let layout = physical_layout(&displays);
+    let mut parts = Vec::new();
+    let mut numbers = Vec::new();
+    for (n, (monitor, physical)) in displays.into_iter().zip(layout).enumerate() {
+        if let Some(part) = rect.intersect(&physical) {
+            parts.push((monitor, physical, part));
+            numbers.push(n + 1);
+        }
+    }
+    if parts.is_empty() {
+        return Err(CaptureError::InvalidArea);
+    }
+    let images = in_parallel(&parts, |(monitor, physical, part)| {
+        //capture_area wants logical pixels relative to the display, see screenshots::Screen::capture_area
+        let s = monitor.scale_factor;
+        backend.capture_area(monitor,
+                             ((part.x - physical.x) as f32 / s) as i32,
+                             ((part.y - physical.y) as f32 / s) as i32,
+                             (part.width as f32 / s) as u32,
+                             (part.height as f32 / s) as u32)
+    })?;
+    let placed = parts.into_iter().map(|(_, _, part)| (part.x, part.y)).zip(images).collect();
+    let monitor = if numbers.len() == 1 { Some(numbers[0]) } else { None };
+    Ok((place(placed)?, monitor))
+}
+
+/** cattura gli schermi in parallelo, nello stesso ordine **/
+pub fn capture_displays(backend: &dyn CaptureBackend, displays: Vec<Monitor>) -> Result<Vec<(Monitor, RgbaImage)>, CaptureError> {
+    let images = in_parallel(&displays, |monitor| backend.capture_display(monitor))?;
+    Ok(displays.into_iter().zip(images).collect())
+}
+
+/** un thread per elemento: su piú schermi la cattura dura quanto quella dello schermo piú lento **/
+fn in_parallel<T: Sync>(items: &[T], capture: impl Fn(&T) -> Result<RgbaImage, CaptureError> + Sync) -> Result<Vec<RgbaImage>, CaptureError> {
+    if let [item] = items {
+        return Ok(vec![capture(item)?]);
+    }
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items.iter().map(|item| scope.spawn(|| capture(item))).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or(Err(CaptureError::Backend("capture thread panicked".to_string()))))
+            .collect()
+    })
+}
+
+/// Places every capture at its display position inside a single image, see `physical_layout`.
+pub fn composite(captures: Vec<(Monitor, RgbaImage)>) -> Result<RgbaImage, CaptureError> {
+    let monitors: Vec<Monitor> = captures.iter().map(|(m, _)| *m).collect();
+    let origins = physical_layout(&monitors).into_iter().map(|r| (r.x, r.y));
+    place(origins.zip(captures.into_iter().map(|(_, img)| img)).collect())
+}
+
+/** unisce le immagini, ognuna con la sua origine in pixel fisici, le parti non coperte restano trasparenti **/
+fn place(captures: Vec<((i32, i32), RgbaImage)>) -> Result<RgbaImage, CaptureError> {
+    let x_min = captures.iter().map(|((x, _), _)| *x).min().ok_or(CaptureError::NoDisplays)?;
+    let y_min = captures.iter().map(|((_, y), _)| *y).min().ok_or(CaptureError::NoDisplays)?;
+    let x_max = captures.iter().map(|((x, _), img)| x + img.width() as i32).max().ok_or(CaptureError::NoDisplays)?;
+    let y_max = captures.iter().map(|((_, y), img)| y + img.height() as i32).max().ok_or(CaptureError::NoDisplays)?;
+
+    if captures.len() == 1 {
+        return Ok(captures.into_iter().next().map(|(_, img)| img).unwrap_or_default());
+    }
+    let mut img = RgbaImage::new((x_max - x_min) as u32, (y_max - y_min) as u32);
+    let stride = img.width() as usize * 4;
+    let buffer: &mut [u8] = &mut img;
+    for ((x, y), capture) in captures {
+        //whole rows at a time, physical_layout keeps the displays apart; if two overlapped anyway
+        //(a backend rounding the size up) the later one would win
+        let row = capture.width() as usize * 4;
+        if row == 0 {
+            continue;
+        }
+        let x = (x - x_min) as usize * 4;
+        for (line_y, line) in capture.as_raw().chunks_exact(row).enumerate() {
+            let start = ((y - y_min) as usize + line_y) * stride + x;
+            buffer[start..start + row].copy_from_slice(line);
+        }
+    }
+    Ok(img)
+}
@@ -0,0 +1,239 @@
+use std::path::{Path, PathBuf};
+use clap::{Parser, Subcommand};
+use image::RgbaImage;
+use crate::myapp::Config;
+use crate::myapp::capturelib::{capture_displays, capture_rect, composite, default_backend, CaptureBackend, CaptureInfo, CaptureMode};
+use crate::myapp::imglib::{format_from_string, AllFormats};
+use crate::myapp::gallerylib::{CaptureIndex, CaptureRecord};
+use crate::myapp::pdflib::{open_entries, report_name, write_report, PageSize};
+use crate::myapp::projectlib::{is_project, open_rendered, Project};
+use crate::myapp::savelib::MySave;
+use crate::myapp::svglib::encode_svg;
+use crate::myapp::windowlib::list_windows;
+
+/// Screenshot utility. Without a subcommand the graphical interface is opened.
+#[derive(Parser)]
+#[command(name = "progetto", version)]
+pub struct Cli {
+    /// Settings file, defaults to the one of the window
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Take a screenshot without opening the window
+    Capture {
+        /// Capture only this display (1 = first display, "all" for every display),
+        /// defaults to the last one chosen in the window
+        #[arg(long, value_parser = parse_monitor)]
+        monitor: Option<usize>,
+        /// Crop the capture to x,y,w,h (in pixels of the captured image)
+        #[arg(long, value_parser = parse_region)]
+        region: Option<(u32, u32, u32, u32)>,
+        /// Capture only the topmost window whose title contains this text (X11)
+        #[arg(long, conflicts_with_all = ["monitor", "region"])]
+        window: Option<String>,
+        /// Leave out the frame drawn by the window manager
+        #[arg(long, requires = "window")]
+        no_decorations: bool,
+        /// Output format (png, jpeg, gif, webp, bmp, tiff, qoi, svg, pdf), defaults to the one in the settings
+        #[arg(long)]
+        format: Option<String>,
+        /// Destination folder or file, defaults to the path in the settings
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Convert an image to another format
+    Convert {
+        input: PathBuf,
+        #[arg(long)]
+        format: String,
+        /// Destination folder or file, defaults to the folder of the input
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Combine saved captures or projects into one PDF report
+    Pdf {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Caption of each input, in the same order (repeat the option)
+        #[arg(long)]
+        caption: Vec<String>,
+        /// Captures per page, from 1 to 4, defaults to the PDF settings
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=4))]
+        per_page: Option<u8>,
+        #[arg(long)]
+        landscape: bool,
+        /// US Letter pages instead of A4
+        #[arg(long)]
+        letter: bool,
+        /// Leave out file names and times under the captures
+        #[arg(long)]
+        no_footer: bool,
+        /// Destination folder or file, defaults to the path in the settings
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+/** esegue il comando con le impostazioni date e ritorna il codice di uscita del processo **/
+pub fn run(command: Command, mut config: Config) -> i32 {
+    let result = match command {
+        Command::Capture { window: Some(title), no_decorations, format, out, .. } => {
+            capture_window(default_backend().as_ref(), &mut config, &title, !no_decorations, format, out)
+        }
+        Command::Capture { monitor, region, window: None, format, out, .. } => {
+            capture(default_backend().as_ref(), &mut config, monitor, region, format, out)
+        }
+        Command::Convert { input, format, out } => convert(&config, &input, &format, out),
+        Command::Pdf { inputs, caption, per_page, landscape, letter, no_footer, out } => {
+            let mut options = config.export.pdf.clone();
+            options.per_page = per_page.unwrap_or(options.per_page);
+            options.landscape |= landscape;
+            if letter {
+                options.page = PageSize::Letter;
+            }
+            options.footer &= !no_footer;
+            open_entries(&inputs, &caption, &config.index_path()).and_then(|entries| {
+                let path = output_path(out.unwrap_or(config.path.clone()), &report_name(), &AllFormats::PDF)?;
+                write_report(&path, &entries, &options).map_err(|e| e.to_string())?;
+                Ok(path)
+            })
+        }
+    };
+    match result {
+        Ok(path) => {
+            println!("{}", path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+fn capture(backend: &dyn CaptureBackend, config: &mut Config, monitor: Option<usize>,
+           region: Option<(u32, u32, u32, u32)>, format: Option<String>, out: Option<PathBuf>) -> Result<PathBuf, String> {
+    let format = parse_format(format.as_deref().unwrap_or(config.format.as_str()))?;
+    let mut displays = backend.displays().map_err(|e| e.to_string())?;
+    let monitor = match monitor {
+        Some(ALL_MONITORS) => None,
+        Some(n) if n > displays.len() => {
+            return Err(format!("monitor {} does not exist, {} available", n, displays.len()));
+        }
+        Some(n) => Some(n),
+        //a remembered display that was disconnected is not an error
+        None => config.monitor.filter(|n| *n <= displays.len()),
+    };
+    if let Some(n) = monitor {
+        displays = vec![displays[n - 1]];
+    }
+    let info = CaptureInfo {
+        mode: if region.is_some() { CaptureMode::Region } else { CaptureMode::Full },
+        monitor: monitor.or(if displays.len() == 1 { Some(1) } else { None }),
+    };
+    let captures = capture_displays(backend, displays).map_err(|e| e.to_string())?;
+    let mut img = composite(captures).map_err(|e| e.to_string())?;
+    if let Some((x, y, w, h)) = region {
+        img = crop(&img, x, y, w, h)?;
+    }
+    save_capture(config, img, info, &format, out)
+}
+
+/** salva una cattura della cli con il nome dal template e la aggiunge alla cronologia **/
+fn save_capture(config: &mut Config, img: RgbaImage, info: CaptureInfo, format: &AllFormats, out: Option<PathBuf>) -> Result<PathBuf, String> {
+    let name = config.next_name(info, img.width(), img.height());
+    let path = output_path(out.unwrap_or(config.path.clone()), &name, format)?;
+    MySave::write_image(img.as_raw(), img.width(), img.height(), &path, format, &config.export).map_err(|e| e.to_string())?;
+    config.advance_counter();
+    if let Err(e) = CaptureIndex::append(&config.index_path(), CaptureRecord::new(path.clone(), img.width(), img.height(), info)) {
+        eprintln!("Unable to update the capture history: {}", e);
+    }
+    Ok(path)
+}
+
+fn capture_window(backend: &dyn CaptureBackend, config: &mut Config, title: &str, decorations: bool,
+                  format: Option<String>, out: Option<PathBuf>) -> Result<PathBuf, String> {
+    let format = parse_format(format.as_deref().unwrap_or(config.format.as_str()))?;
+    let windows = list_windows().map_err(|e| e.to_string())?;
+    let window = windows
+        .iter()
+        .find(|w| w.title.to_lowercase().contains(&title.to_lowercase()))
+        .ok_or_else(|| {
+            let titles: Vec<&str> = windows.iter().map(|w| w.title.as_str()).filter(|t| !t.is_empty()).collect();
+            format!("no window titled '{}', open windows: {}", title, titles.join(", "))
+        })?;
+    let (img, monitor) = capture_rect(backend, window.rect(decorations)).map_err(|e| e.to_string())?;
+    save_capture(config, img, CaptureInfo { mode: CaptureMode::Window, monitor }, &format, out)
+}
+
+fn convert(config: &Config, input: &Path, format: &str, out: Option<PathBuf>) -> Result<PathBuf, String> {
+    let format = parse_format(format)?;
+    let name = input.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(MySave::default_name);
+    let dir = input.parent().map(Path::to_path_buf).unwrap_or_default();
+    let path = output_path(out.unwrap_or(dir), &name, &format)?;
+    if format == AllFormats::SVG && is_project(input) {
+        //annotations of a project stay shapes
+        let project = Project::load(input)?;
+        let vector = project.painting.vector_image(project.image);
+        MySave::write_atomically(&path, |file| Ok(encode_svg(&vector, file)?)).map_err(|e| e.to_string())?;
+        return Ok(path);
+    }
+    let img = open_rendered(input)?;
+    MySave::write_image(img.as_raw(), img.width(), img.height(), &path, &format, &config.export).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn crop(img: &RgbaImage, x: u32, y: u32, w: u32, h: u32) -> Result<RgbaImage, String> {
+    //checked, a region near u32::MAX would wrap around and pass
+    if w == 0 || h == 0 || x.checked_add(w).is_none_or(|r| r > img.width()) || y.checked_add(h).is_none_or(|b| b > img.height()) {
+        return Err(format!("region {},{},{},{} is outside the {}x{} capture", x, y, w, h, img.width(), img.height()));
+    }
+    Ok(image::imageops::crop_imm(img, x, y, w, h).to_image())
+}
+
+/** se `out` é una cartella il nome viene generato, altrimenti viene usato cosí com'é **/
+fn output_path(out: PathBuf, name: &str, format: &AllFormats) -> Result<PathBuf, String> {
+    if out.is_dir() || out.extension().is_none() {
+        std::fs::create_dir_all(&out).map_err(|e| e.to_string())?;
+        MySave::unique_path(out, name, format).map_err(|e| e.to_string())
+    } else {
+        Ok(out)
+    }
+}
+
+fn parse_format(format: &str) -> Result<AllFormats, String> {
+    format_from_string(format.to_lowercase().as_str())
+        .ok_or(format!("unsupported format '{}'", format))
+}
+
+/// Value of `--monitor all`.
+const ALL_MONITORS: usize = 0;
+
+fn parse_monitor(monitor: &str) -> Result<usize, String> {
+    if monitor.eq_ignore_ascii_case("all") {
+        return Ok(ALL_MONITORS);
+    }
+    match monitor.parse::<usize>() {
+        Ok(0) => Err("displays are numbered from 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_region(region: &str) -> Result<(u32, u32, u32, u32), String> {
+    let values = region
+        .split(',')
+        .map(|v| v.trim().parse::<u32>().map_err(|e| format!("'{}': {}", v, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values.as_slice() {
+        [x, y, w, h] => Ok((*x, *y, *w, *h)),
+        _ => Err("expected x,y,w,h".to_string()),
+    }
+}
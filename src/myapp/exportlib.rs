@@ -0,0 +1,173 @@
+use std::io::Write;
+use egui::{color_picker, Slider, Ui};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::error::{EncodingError, ImageFormatHint, ParameterError, ParameterErrorKind};
+use image::{ImageEncoder, ImageError, ImageFormat, RgbaImage};
+use serde::{Serialize, Deserialize};
+use crate::myapp::giflib::GifOptions;
+use crate::myapp::imglib::AllFormats;
+use crate::myapp::pdflib::PdfOptions;
+
+/// Encoder settings of the formats that have any, saved in `Config` as defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub jpeg: JpegOptions,
+    pub png: PngOptions,
+    pub gif: GifOptions,
+    pub pdf: PdfOptions,
+}
+
+impl ExportOptions {
+    pub fn has_options(format: &AllFormats) -> bool {
+        matches!(format, AllFormats::JPEG | AllFormats::PNG | AllFormats::GIF | AllFormats::PDF)
+    }
+
+    /** opzioni del formato scelto, niente per gli altri **/
+    pub fn options_ui(&mut self, ui: &mut Ui, format: &AllFormats) {
+        match format {
+            AllFormats::JPEG => self.jpeg.options_ui(ui),
+            AllFormats::PNG => self.png.options_ui(ui),
+            AllFormats::GIF => self.gif.options_ui(ui),
+            AllFormats::PDF => self.pdf.options_ui(ui),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Subsampling {
+    S444,
+    S422,
+    S420,
+}
+
+impl Subsampling {
+    pub fn to_name(self) -> &'static str {
+        match self {
+            Subsampling::S444 => "4:4:4",
+            Subsampling::S422 => "4:2:2",
+            Subsampling::S420 => "4:2:0",
+        }
+    }
+}
+
+/// JPEG has no alpha channel: transparent pixels are blended over `background`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct JpegOptions {
+    pub quality: u8,
+    pub subsampling: Subsampling,
+    pub background: [u8; 3],
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self { quality: 90, subsampling: Subsampling::S420, background: [255, 255, 255] }
+    }
+}
+
+impl JpegOptions {
+    pub fn options_ui(&mut self, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            ui.add(Slider::new(&mut self.quality, 1..=100).text("Quality"));
+            ui.horizontal(|ui| {
+                for subsampling in [Subsampling::S444, Subsampling::S422, Subsampling::S420] {
+                    ui.selectable_value(&mut self.subsampling, subsampling, subsampling.to_name());
+                }
+            });
+            ui.horizontal(|ui| {
+                color_picker::color_edit_button_srgb(ui, &mut self.background);
+                ui.label("Background");
+            });
+        });
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PngFilter {
+    NoFilter,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    Adaptive,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self { compression: PngCompression::Default, filter: PngFilter::Adaptive }
+    }
+}
+
+impl PngOptions {
+    pub fn options_ui(&mut self, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Compression");
+                for compression in [PngCompression::Fast, PngCompression::Default, PngCompression::Best] {
+                    ui.selectable_value(&mut self.compression, compression, format!("{:?}", compression));
+                }
+            });
+            egui::ComboBox::from_label("Filter")
+                .selected_text(format!("{:?}", self.filter))
+                .show_ui(ui, |ui| {
+                    for filter in [PngFilter::NoFilter, PngFilter::Sub, PngFilter::Up, PngFilter::Avg, PngFilter::Paeth, PngFilter::Adaptive] {
+                        ui.selectable_value(&mut self.filter, filter, format!("{:?}", filter));
+                    }
+                });
+        });
+    }
+}
+
+pub fn encode_jpeg<W: Write>(img: &RgbaImage, options: &JpegOptions, writer: W) -> Result<(), ImageError> {
+    if img.width() > u16::MAX as u32 || img.height() > u16::MAX as u32 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+    }
+    let background = options.background.map(|c| c as f32);
+    let rgb: Vec<u8> = img.pixels().flat_map(|p| {
+        let alpha = p[3] as f32 / 255.;
+        [0, 1, 2].map(|i| (p[i] as f32 * alpha + background[i] * (1. - alpha)).round() as u8)
+    }).collect();
+    let mut encoder = jpeg_encoder::Encoder::new(writer, options.quality.clamp(1, 100));
+    encoder.set_sampling_factor(match options.subsampling {
+        Subsampling::S444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+        Subsampling::S422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+        Subsampling::S420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+    });
+    encoder
+        .encode(&rgb, img.width() as u16, img.height() as u16, jpeg_encoder::ColorType::Rgb)
+        .map_err(|e| ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(ImageFormat::Jpeg), e)))
+}
+
+pub fn encode_png<W: Write>(img: &RgbaImage, options: &PngOptions, writer: W) -> Result<(), ImageError> {
+    let compression = match options.compression {
+        PngCompression::Fast => CompressionType::Fast,
+        PngCompression::Default => CompressionType::Default,
+        PngCompression::Best => CompressionType::Best,
+    };
+    let filter = match options.filter {
+        PngFilter::NoFilter => FilterType::NoFilter,
+        PngFilter::Sub => FilterType::Sub,
+        PngFilter::Up => FilterType::Up,
+        PngFilter::Avg => FilterType::Avg,
+        PngFilter::Paeth => FilterType::Paeth,
+        PngFilter::Adaptive => FilterType::Adaptive,
+    };
+    PngEncoder::new_with_quality(writer, compression, filter)
+        .write_image(img.as_raw(), img.width(), img.height(), image::ColorType::Rgba8)
+}
@@ -0,0 +1,49 @@
+use egui::{Pos2, Vec2};
+
+/// The window operations used by the layouts. `eframe::Frame` is the real window,
+/// the headless tests pass one that only remembers what was asked.
+pub trait AppFrame {
+    /// inner size of the window, in points
+    fn window_size(&self) -> Vec2;
+    fn monitor_size(&self) -> Option<Vec2>;
+    fn set_visible(&mut self, visible: bool);
+    fn set_minimized(&mut self, minimized: bool);
+    fn set_decorations(&mut self, decorations: bool);
+    fn set_window_size(&mut self, size: Vec2);
+    fn set_window_pos(&mut self, pos: Pos2);
+    fn focus(&mut self);
+}
+
+impl AppFrame for eframe::Frame {
+    fn window_size(&self) -> Vec2 {
+        self.info().window_info.size
+    }
+
+    fn monitor_size(&self) -> Option<Vec2> {
+        self.info().window_info.monitor_size
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        eframe::Frame::set_visible(self, visible)
+    }
+
+    fn set_minimized(&mut self, minimized: bool) {
+        eframe::Frame::set_minimized(self, minimized)
+    }
+
+    fn set_decorations(&mut self, decorations: bool) {
+        eframe::Frame::set_decorations(self, decorations)
+    }
+
+    fn set_window_size(&mut self, size: Vec2) {
+        eframe::Frame::set_window_size(self, size)
+    }
+
+    fn set_window_pos(&mut self, pos: Pos2) {
+        eframe::Frame::set_window_pos(self, pos)
+    }
+
+    fn focus(&mut self) {
+        eframe::Frame::focus(self)
+    }
+}
@@ -0,0 +1,479 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+use arboard::{Clipboard, ImageData};
+use chrono::{DateTime, Local};
+use egui::{CentralPanel, Color32, ComboBox, Context, ScrollArea, TextureHandle, Ui, Vec2};
+use image::RgbaImage;
+use serde::{Serialize, Deserialize};
+use crate::myapp::{PADDING, render_header};
+use crate::myapp::capturelib::CaptureInfo;
+use crate::myapp::framelib::AppFrame;
+use crate::myapp::imglib::{load_image_from_memory, AllFormats};
+use crate::myapp::statelib::{AppEvent, AppState};
+use crate::myapp::paintlib::Painting;
+use crate::myapp::pdflib::{open_entries, report_name, write_report, PdfOptions};
+use crate::myapp::projectlib::{is_project, open_rendered, Project, PROJECT_EXTENSION};
+use crate::myapp::savelib::{MySave, SaveError, SaveResult, Saved};
+
+/// Side of the square a thumbnail fits in.
+const THUMBNAIL_SIZE: u32 = 160;
+/// Thumbnails decoded per frame, so a long history does not freeze the window.
+const THUMBNAILS_PER_FRAME: usize = 2;
+
+/// A saved capture, as remembered by the history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CaptureRecord {
+    pub path: PathBuf,
+    pub timestamp: DateTime<Local>,
+    pub width: u32,
+    pub height: u32,
+    pub capture: CaptureInfo,
+    /// file extension, e.g. "png"
+    pub format: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl CaptureRecord {
+    pub fn new(path: PathBuf, width: u32, height: u32, capture: CaptureInfo) -> Self {
+        let format = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        //the cli may save to a relative path
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        Self { path, timestamp: Local::now(), width, height, capture, format, tags: vec![] }
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+    }
+
+    /** vero se il nome del file o un tag contengono `search` (senza distinguere le maiuscole) **/
+    fn matches(&self, search: &str) -> bool {
+        let search = search.trim().to_lowercase();
+        search.is_empty()
+            || self.file_name().to_lowercase().contains(&search)
+            || self.tags.iter().any(|t| t.to_lowercase().contains(&search))
+    }
+}
+
+/// Index of every capture, stored as JSON next to the settings file (`Config::index_path`).
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CaptureIndex {
+    pub captures: Vec<CaptureRecord>,
+}
+
+impl CaptureIndex {
+    /** un indice mancante é vuoto, uno illeggibile é un errore per non sovrascriverlo **/
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let data = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        //same temp + rename as the saved images, a crash never leaves half an index
+        let temp = path.with_extension("json.part");
+        fs::write(&temp, data).and_then(|_| fs::rename(&temp, path)).map_err(|e| e.to_string())
+    }
+
+    /** aggiunge una cattura rileggendo l'indice, che puó essere stato modificato dalla cli **/
+    pub fn append(path: &Path, record: CaptureRecord) -> Result<(), String> {
+        Self::update(path, |index| index.captures.push(record)).map(|_| ())
+    }
+
+    /** applica `change` all'indice appena riletto e lo salva, ritorna l'indice salvato:
+    le catture aggiunte da altri nel frattempo restano **/
+    pub fn update(path: &Path, change: impl FnOnce(&mut Self)) -> Result<Self, String> {
+        let mut index = Self::load(path)?;
+        change(&mut index);
+        index.store(path)?;
+        Ok(index)
+    }
+}
+
+/// Buttons of a capture that change something outside the gallery.
+enum CardAction {
+    Open,
+    Copy,
+    Reveal,
+    AddToReport,
+}
+
+pub struct MyGallery {
+    index: CaptureIndex,
+    index_path: PathBuf,
+    error: Option<String>,
+    search: String,
+    format: Option<String>,
+    /// `Some(None)` filters the captures spanning all the displays
+    monitor: Option<Option<usize>>,
+    thumbnails: HashMap<PathBuf, Option<TextureHandle>>,
+    /// text of the tag field of each capture while it is edited
+    tags: HashMap<PathBuf, String>,
+    confirm_delete: Option<PathBuf>,
+    /// captures of the next PDF report, in page order, with their captions
+    report: Vec<(PathBuf, String)>,
+    /// files chosen in the file picker, which runs on another thread
+    picked: Option<Receiver<Vec<PathBuf>>>,
+    /// where reports are saved and how they are laid out
+    path: PathBuf,
+    pdf: PdfOptions,
+    tx: Sender<SaveResult>,
+}
+
+impl MyGallery {
+    pub fn new(index_path: PathBuf, path: PathBuf, pdf: PdfOptions, tx: Sender<SaveResult>) -> Self {
+        let (index, error) = match CaptureIndex::load(&index_path) {
+            Ok(index) => (index, None),
+            Err(e) => (CaptureIndex::default(), Some(e)),
+        };
+        Self {
+            index,
+            index_path,
+            error,
+            search: String::new(),
+            format: None,
+            monitor: None,
+            thumbnails: HashMap::new(),
+            tags: HashMap::new(),
+            confirm_delete: None,
+            report: vec![],
+            picked: None,
+            path,
+            pdf,
+            tx,
+        }
+    }
+
+    /** le modifiche vanno sull'indice su disco, quello caricato all'apertura puó non avere
+    le catture salvate dopo **/
+    fn store(&mut self, change: impl FnOnce(&mut CaptureIndex)) {
+        match CaptureIndex::update(&self.index_path, change) {
+            Ok(index) => self.index = index,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    pub fn captures(&self) -> &[CaptureRecord] {
+        &self.index.captures
+    }
+
+    pub fn set_tags(&mut self, path: &Path, tags: Vec<String>) {
+        self.store(|index| {
+            if let Some(r) = index.captures.iter_mut().find(|r| r.path == path) {
+                r.tags = tags;
+            }
+        });
+    }
+
+    /** ritorna la cattura da riaprire nell'editor, se scelta, con le annotazioni se é un progetto **/
+    pub fn gallery_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame, state: &mut AppState,
+                          clipboard: &mut Option<Clipboard>) -> Option<(RgbaImage, CaptureInfo, Option<Painting>)> {
+        let mut opened = None;
+        CentralPanel::default().show(ctx, |ui| {
+            render_header(ui, "HISTORY");
+            self.filters(ui);
+            if let Some(e) = &self.error {
+                ui.colored_label(Color32::LIGHT_RED, e);
+            }
+            ui.add_space(PADDING);
+            ui.separator();
+            let mut loaded = 0;
+            let visible: Vec<CaptureRecord> = self.index.captures
+                .iter()
+                .rev()
+                .filter(|r| r.matches(&self.search))
+                .filter(|r| self.format.as_ref().is_none_or(|f| *f == r.format))
+                .filter(|r| self.monitor.is_none_or(|m| m == r.capture.monitor))
+                .cloned()
+                .collect();
+            let mut action = None;
+            //room for the report list and the buttons at the bottom
+            let reserved = 75. + (self.report.len() as f32 * 24.).min(100.);
+            ScrollArea::vertical().max_height(ui.available_height() - reserved).show(ui, |ui| {
+                if visible.is_empty() {
+                    ui.weak("No captures");
+                }
+                ui.horizontal_wrapped(|ui| {
+                    for record in &visible {
+                        if let Some(a) = self.card(ui, record, &mut loaded) {
+                            action = Some((a, record));
+                        }
+                    }
+                });
+            });
+            match action {
+                Some((CardAction::Open, record)) => match open_capture(&record.path, record.capture) {
+                    Ok(capture) => {
+                        opened = Some(capture);
+                        state.handle(AppEvent::Back, _frame);
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                },
+                Some((CardAction::Copy, record)) => self.copy(&record.path, clipboard),
+                Some((CardAction::Reveal, record)) => {
+                    let folder = record.path.parent().unwrap_or(Path::new("."));
+                    if let Err(e) = opener::open(folder) {
+                        self.error = Some(e.to_string());
+                    }
+                }
+                Some((CardAction::AddToReport, record)) => self.report.push((record.path.clone(), String::new())),
+                None => {}
+            }
+            ui.separator();
+            self.report_ui(ui);
+            ui.horizontal(|ui| {
+                if ui.button("↩").clicked() {
+                    state.handle(AppEvent::Back, _frame);
+                }
+            });
+        });
+        opened
+    }
+
+    fn filters(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("🔍 name or tag").desired_width(150.));
+            let mut formats: Vec<String> = self.index.captures.iter().map(|r| r.format.clone()).collect();
+            formats.sort();
+            formats.dedup();
+            ComboBox::from_id_source("history_format")
+                .selected_text(self.format.clone().unwrap_or("All formats".to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.format, None, "All formats");
+                    for format in formats {
+                        ui.selectable_value(&mut self.format, Some(format.clone()), format);
+                    }
+                });
+            let mut monitors: Vec<Option<usize>> = self.index.captures.iter().map(|r| r.capture.monitor).collect();
+            monitors.sort();
+            monitors.dedup();
+            ComboBox::from_id_source("history_monitor")
+                .selected_text(self.monitor.map(monitor_name).unwrap_or("Any monitor".to_string()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.monitor, None, "Any monitor");
+                    for monitor in monitors {
+                        ui.selectable_value(&mut self.monitor, Some(monitor), monitor_name(monitor));
+                    }
+                });
+        });
+    }
+
+    /** miniatura, dati, tag e pulsanti di una cattura **/
+    fn card(&mut self, ui: &mut Ui, record: &CaptureRecord, loaded: &mut usize) -> Option<CardAction> {
+        let exists = record.path.exists();
+        let mut action = None;
+        ui.group(|ui| {
+            ui.set_width(THUMBNAIL_SIZE as f32);
+            ui.vertical(|ui| {
+                let size = Vec2::splat(THUMBNAIL_SIZE as f32);
+                if !self.thumbnails.contains_key(&record.path) && exists && *loaded < THUMBNAILS_PER_FRAME {
+                    *loaded += 1;
+                    let texture = load_thumbnail(&record.path)
+                        .map(|t| ui.ctx().load_texture(record.path.to_string_lossy(), load_image_from_memory(t), Default::default()));
+                    self.thumbnails.insert(record.path.clone(), texture);
+                }
+                match self.thumbnails.get(&record.path) {
+                    Some(Some(texture)) => {
+                        let [w, h] = texture.size();
+                        let scale = (size.x / w as f32).min(size.y / h as f32);
+                        ui.add_sized(size, egui::Image::new(texture, Vec2::new(w as f32, h as f32) * scale));
+                    }
+                    loaded_thumbnail => {
+                        let text = match (exists, loaded_thumbnail) {
+                            (false, _) => "missing",
+                            (true, Some(None)) => "no preview",
+                            (true, _) => "…",
+                        };
+                        ui.add_sized(size, egui::Label::new(egui::RichText::new(text).weak()));
+                    }
+                }
+                ui.label(record.file_name()).on_hover_text(record.path.display().to_string());
+                ui.weak(record.timestamp.format("%Y-%m-%d %H:%M:%S").to_string());
+                ui.weak(format!("{}×{} · {} · {}", record.width, record.height, monitor_name(record.capture.monitor),
+                                record.capture.mode.to_name()));
+
+                let tags = self.tags.entry(record.path.clone()).or_insert_with(|| record.tags.join(", "));
+                let response = ui.add(egui::TextEdit::singleline(tags).hint_text("tags"));
+                if response.lost_focus() {
+                    let tags: Vec<String> = tags.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+                    if record.tags != tags {
+                        self.set_tags(&record.path, tags);
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    ui.add_enabled_ui(exists, |ui| {
+                        if ui.button("↗").on_hover_text("Open").clicked() {
+                            action = Some(CardAction::Open);
+                        }
+                        if ui.button("📋").on_hover_text("Copy").clicked() {
+                            action = Some(CardAction::Copy);
+                        }
+                        if ui.button("🗁").on_hover_text("Show in folder").clicked() {
+                            action = Some(CardAction::Reveal);
+                        }
+                        if ui.button("➕").on_hover_text("Add to PDF report").clicked() {
+                            action = Some(CardAction::AddToReport);
+                        }
+                    });
+                    if self.confirm_delete.as_ref() == Some(&record.path) {
+                        if ui.button("✔").on_hover_text("Delete the file").clicked() {
+                            self.delete(&record.path);
+                        }
+                        if ui.button("✖").clicked() {
+                            self.confirm_delete = None;
+                        }
+                    } else if ui.button("🗑").on_hover_text(if exists { "Delete" } else { "Forget" }).clicked() {
+                        self.confirm_delete = Some(record.path.clone());
+                    }
+                });
+            });
+        });
+        action
+    }
+
+    /** catture del report, con didascalia e ordine modificabili, e pulsanti per aggiungere file e creare il pdf **/
+    fn report_ui(&mut self, ui: &mut Ui) {
+        if let Some(picked) = &self.picked {
+            if let Ok(paths) = picked.try_recv() {
+                self.report.extend(paths.into_iter().map(|p| (p, String::new())));
+                self.picked = None;
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.strong(format!("PDF report ({})", self.report.len()));
+            if ui.add_enabled(self.picked.is_none(), egui::Button::new("Add files…")).clicked() {
+                self.pick_files(ui.ctx().clone());
+            }
+            if ui.add_enabled(!self.report.is_empty(), egui::Button::new("Create PDF")).clicked() {
+                self.create_report(ui.ctx().clone());
+            }
+            if ui.add_enabled(!self.report.is_empty(), egui::Button::new("Clear")).clicked() {
+                self.report.clear();
+            }
+        });
+        let mut swap = None;
+        let mut remove = None;
+        let last = self.report.len().saturating_sub(1);
+        ScrollArea::vertical().id_source("report").max_height(100.).show(ui, |ui| {
+            for (i, (path, caption)) in self.report.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(i > 0, egui::Button::new("⏶")).clicked() {
+                        swap = Some(i - 1);
+                    }
+                    if ui.add_enabled(i < last, egui::Button::new("⏷")).clicked() {
+                        swap = Some(i);
+                    }
+                    if ui.button("✖").clicked() {
+                        remove = Some(i);
+                    }
+                    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    ui.label(name).on_hover_text(path.display().to_string());
+                    ui.add(egui::TextEdit::singleline(caption).hint_text("caption").desired_width(f32::INFINITY));
+                });
+            }
+        });
+        if let Some(i) = swap {
+            self.report.swap(i, i + 1);
+        }
+        if let Some(i) = remove {
+            self.report.remove(i);
+        }
+    }
+
+    /** il selettore di file del sistema blocca finché non viene chiuso, quindi gira in un task **/
+    fn pick_files(&mut self, ctx: Context) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.picked = Some(rx);
+        let dialog = rfd::AsyncFileDialog::new()
+            .set_title("Add to PDF report")
+            .add_filter("Captures", &["png", "jpeg", "jpg", "gif", "webp", "bmp", "tiff", "qoi", PROJECT_EXTENSION])
+            .set_directory(&self.path);
+        tokio::spawn(async move {
+            let paths = dialog.pick_files().await
+                .map(|files| files.iter().map(|f| f.path().to_path_buf()).collect())
+                .unwrap_or_default();
+            let _ = tx.send(paths);
+            ctx.request_repaint();
+        });
+    }
+
+    /** crea il pdf nella cartella dei salvataggi, il risultato arriva come quello degli altri salvataggi ma segnato come report **/
+    fn create_report(&self, ctx: Context) {
+        let (paths, captions): (Vec<PathBuf>, Vec<String>) = self.report.iter().cloned().unzip();
+        let dir = self.path.clone();
+        let index_path = self.index_path.clone();
+        let options = self.pdf.clone();
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let instant = std::time::Instant::now();
+            let result = open_entries(&paths, &captions, &index_path)
+                .map_err(|e| SaveError::Io(std::io::Error::other(e)))
+                .and_then(|entries| {
+                    let path = MySave::unique_path(dir, &report_name(), &AllFormats::PDF)?;
+                    let size = write_report(&path, &entries, &options)?;
+                    let (width, height) = entries.first().map(|e| e.image.dimensions()).unwrap_or_default();
+                    Ok(Saved { path, size, width, height, duration: instant.elapsed(), report: true })
+                });
+            let _ = tx.send(result);
+            ctx.request_repaint();
+        });
+    }
+
+    fn copy(&mut self, path: &Path, clipboard: &mut Option<Clipboard>) {
+        let result = open_rendered(path).and_then(|rgba| {
+            let img_data = ImageData {
+                width: rgba.width() as usize,
+                height: rgba.height() as usize,
+                bytes: Cow::from(rgba.into_raw()),
+            };
+            clipboard.as_mut().ok_or("clipboard not available".to_string())?
+                .set_image(img_data)
+                .map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+
+    /** cancella il file (se c'é ancora) e lo toglie dalla cronologia **/
+    fn delete(&mut self, path: &Path) {
+        self.confirm_delete = None;
+        if path.exists() {
+            if let Err(e) = fs::remove_file(path) {
+                self.error = Some(e.to_string());
+                return;
+            }
+        }
+        self.store(|index| index.captures.retain(|r| r.path != path));
+        self.thumbnails.remove(path);
+        self.tags.remove(path);
+    }
+}
+
+fn monitor_name(monitor: Option<usize>) -> String {
+    monitor.map(|n| format!("Monitor {}", n)).unwrap_or("All monitors".to_string())
+}
+
+fn open_capture(path: &Path, capture: CaptureInfo) -> Result<(RgbaImage, CaptureInfo, Option<Painting>), String> {
+    if is_project(path) {
+        let project = Project::load(path)?;
+        Ok((project.image, project.capture, Some(project.painting)))
+    } else {
+        let img = image::open(path).map_err(|e| e.to_string())?;
+        Ok((img.to_rgba8(), capture, None))
+    }
+}
+
+fn load_thumbnail(path: &Path) -> Option<RgbaImage> {
+    open_rendered(path).ok().map(|i| image::imageops::thumbnail(&i, THUMBNAIL_SIZE, THUMBNAIL_SIZE))
+}
@@ -0,0 +1,234 @@
+use std::borrow::Cow;
+use std::io::Write;
+use color_quant::NeuQuant;
+use egui::{Slider, Ui};
+use image::error::{EncodingError, ImageFormatHint, ParameterError, ParameterErrorKind};
+use image::{ImageError, ImageFormat, RgbaImage};
+use serde::{Serialize, Deserialize};
+
+/// How the palette of the GIF is chosen.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Quantizer {
+    NeuQuant,
+    MedianCut,
+}
+
+impl Quantizer {
+    pub fn to_name(self) -> &'static str {
+        match self {
+            Quantizer::NeuQuant => "NeuQuant",
+            Quantizer::MedianCut => "Median cut",
+        }
+    }
+}
+
+/// GIF export settings, `scale` applies to both width and height.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct GifOptions {
+    pub scale: f32,
+    pub colors: u16,
+    pub quantizer: Quantizer,
+    pub dither: bool,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self { scale: 1., colors: 256, quantizer: Quantizer::NeuQuant, dither: false }
+    }
+}
+
+impl GifOptions {
+    pub fn options_ui(&mut self, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            ui.add(Slider::new(&mut self.scale, 0.1..=1.0).text("Scale"));
+            ui.add(Slider::new(&mut self.colors, 2..=256).text("Colors"));
+            ui.horizontal(|ui| {
+                for quantizer in [Quantizer::NeuQuant, Quantizer::MedianCut] {
+                    ui.selectable_value(&mut self.quantizer, quantizer, quantizer.to_name());
+                }
+            });
+            ui.checkbox(&mut self.dither, "Floyd–Steinberg dithering");
+        });
+    }
+}
+
+/// Pixels with less alpha than this become the transparent color of the GIF.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/** scala, riduce i colori e scrive l'immagine come gif di un solo frame **/
+pub fn encode_gif<W: Write>(img: &RgbaImage, options: &GifOptions, writer: W) -> Result<(), ImageError> {
+    let width = ((img.width() as f32 * options.scale).round() as u32).max(1);
+    let height = ((img.height() as f32 * options.scale).round() as u32).max(1);
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+    }
+    let img = if (width, height) != img.dimensions() {
+        Cow::Owned(image::imageops::resize(img, width, height, image::imageops::FilterType::Lanczos3))
+    } else {
+        Cow::Borrowed(img)
+    };
+
+    let transparent = img.pixels().any(|p| p[3] < ALPHA_THRESHOLD);
+    //one entry of the palette is kept for the transparent pixels
+    let colors = (options.colors.clamp(2, 256) - transparent as u16) as usize;
+    let opaque = img.pixels().filter(|p| p[3] >= ALPHA_THRESHOLD).map(|p| [p[0], p[1], p[2]]);
+    let mut palette = match options.quantizer {
+        Quantizer::NeuQuant => neuquant(opaque, colors),
+        Quantizer::MedianCut => median_cut(opaque, colors),
+    };
+    if palette.is_empty() {
+        palette.push([0, 0, 0]);
+    }
+    let transparent_index = transparent.then_some(palette.len() as u8);
+    let indices = map_to_palette(&img, &palette, transparent_index, options.dither);
+    if transparent {
+        palette.push([0, 0, 0]);
+    }
+
+    let to_image_error = |e: gif::EncodingError| {
+        ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(ImageFormat::Gif), e))
+    };
+    let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &palette.concat())
+        .map_err(to_image_error)?;
+    let frame = gif::Frame {
+        width: width as u16,
+        height: height as u16,
+        buffer: Cow::Owned(indices),
+        transparent: transparent_index,
+        ..gif::Frame::default()
+    };
+    encoder.write_frame(&frame).map_err(to_image_error)
+}
+
+fn neuquant(pixels: impl Iterator<Item = [u8; 3]>, colors: usize) -> Vec<[u8; 3]> {
+    let rgba: Vec<u8> = pixels.flat_map(|[r, g, b]| [r, g, b, 255]).collect();
+    if rgba.is_empty() {
+        return vec![];
+    }
+    NeuQuant::new(10, colors, &rgba)
+        .color_map_rgb()
+        .chunks_exact(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect()
+}
+
+/** indice nell'istogramma con 5 bit per canale **/
+fn cell(color: [u8; 3]) -> usize {
+    ((color[0] as usize >> 3) << 10) | ((color[1] as usize >> 3) << 5) | (color[2] as usize >> 3)
+}
+
+fn median_cut(pixels: impl Iterator<Item = [u8; 3]>, colors: usize) -> Vec<[u8; 3]> {
+    let mut histogram = vec![0u32; 1 << 15];
+    pixels.for_each(|p| histogram[cell(p)] += 1);
+    let cells: Vec<([u8; 3], u32)> = histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(i, count)| ([(i >> 10) as u8, (i >> 5 & 31) as u8, (i & 31) as u8], *count))
+        .collect();
+    if cells.is_empty() {
+        return vec![];
+    }
+
+    let mut boxes = vec![cells];
+    while boxes.len() < colors {
+        //the box with the widest channel is split at the median of its pixels
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (axis, range) = (0..3)
+                    .map(|axis| {
+                        let min = b.iter().map(|(c, _)| c[axis]).min().unwrap_or(0);
+                        let max = b.iter().map(|(c, _)| c[axis]).max().unwrap_or(0);
+                        (axis, max - min)
+                    })
+                    .max_by_key(|(_, range)| *range)
+                    .unwrap_or((0, 0));
+                (i, axis, range)
+            })
+            .max_by_key(|(_, _, range)| *range);
+        let Some((i, axis, _)) = widest else { break };
+        let mut cells = boxes.swap_remove(i);
+        cells.sort_unstable_by_key(|(c, _)| c[axis]);
+        let total: u32 = cells.iter().map(|(_, count)| count).sum();
+        let mut sum = 0;
+        let median = cells.iter().position(|(_, count)| {
+            sum += count;
+            sum >= total / 2
+        }).unwrap_or(0);
+        let rest = cells.split_off((median + 1).clamp(1, cells.len() - 1));
+        boxes.push(cells);
+        boxes.push(rest);
+    }
+
+    boxes.iter().map(|cells| {
+        let total: u64 = cells.iter().map(|(_, count)| *count as u64).sum();
+        let mut color = [0u8; 3];
+        for (axis, channel) in color.iter_mut().enumerate() {
+            let sum: u64 = cells.iter().map(|(c, count)| ((c[axis] as u64) << 3 | 4) * *count as u64).sum();
+            *channel = (sum / total) as u8;
+        }
+        color
+    }).collect()
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, p)| {
+            (0..3).map(|i| (p[i] as i32 - color[i] as i32).pow(2)).sum::<i32>()
+        })
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
+
+/** indice del colore di ogni pixel, con la diffusione dell'errore di Floyd–Steinberg se richiesta **/
+fn map_to_palette(img: &RgbaImage, palette: &[[u8; 3]], transparent: Option<u8>, dither: bool) -> Vec<u8> {
+    //nearest color cached for each cell of the histogram, screenshots reuse few colors
+    let mut lookup: Vec<Option<u8>> = vec![None; 1 << 15];
+    let mut index_of = |color: [u8; 3]| {
+        *lookup[cell(color)].get_or_insert_with(|| nearest(palette, color))
+    };
+
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut error = vec![[0f32; 3]; if dither { width * 2 } else { 0 }];
+    let mut indices = Vec::with_capacity(width * height);
+    for y in 0..height {
+        if dither {
+            //error[..width] is this row, error[width..] the next one
+            error.copy_within(width.., 0);
+            error[width..].fill([0.; 3]);
+        }
+        for x in 0..width {
+            let p = img.get_pixel(x as u32, y as u32);
+            if p[3] < ALPHA_THRESHOLD {
+                indices.push(transparent.unwrap_or(0));
+                continue;
+            }
+            if !dither {
+                indices.push(index_of([p[0], p[1], p[2]]));
+                continue;
+            }
+            let wanted = [0, 1, 2].map(|i| (p[i] as f32 + error[x][i]).clamp(0., 255.));
+            let index = index_of(wanted.map(|c| c.round() as u8));
+            indices.push(index);
+            let chosen = palette[index as usize];
+            for i in 0..3 {
+                let diff = wanted[i] - chosen[i] as f32;
+                if x + 1 < width {
+                    error[x + 1][i] += diff * 7. / 16.;
+                    error[width + x + 1][i] += diff / 16.;
+                }
+                if x > 0 {
+                    error[width + x - 1][i] += diff * 3. / 16.;
+                }
+                error[width + x][i] += diff * 5. / 16.;
+            }
+        }
+    }
+    indices
+}
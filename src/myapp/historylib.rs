@@ -0,0 +1,137 @@
+use egui::{Pos2, Stroke};
+use image::RgbaImage;
+use crate::myapp::paintlib::Shapes;
+
+/// A drawn annotation: kind, points in canvas coordinates and stroke.
+pub type Annotation = (Shapes, Vec<Pos2>, Stroke);
+
+/// A change of the annotations (and, for a crop, of the image) that can be reverted.
+#[derive(PartialEq)]
+pub enum Command {
+    Add(usize, Annotation),
+    Delete(usize, Annotation),
+    /// Move, resize or style change: annotation before and after.
+    Edit(usize, Annotation, Annotation, String),
+    Clear(Vec<Annotation>),
+    Crop {
+        image: RgbaImage,
+        cropped: RgbaImage,
+        shapes: Vec<Annotation>,
+        cropped_shapes: Vec<Annotation>,
+    },
+}
+
+impl Command {
+    pub fn name(&self) -> String {
+        match self {
+            Command::Add(_, (shape, _, _)) => format!("Add {}", shape.to_name()),
+            Command::Delete(_, (shape, _, _)) => format!("Delete {}", shape.to_name()),
+            Command::Edit(_, (shape, _, _), _, what) => format!("{} {}", what, shape.to_name()),
+            Command::Clear(_) => "Clear".to_string(),
+            Command::Crop { .. } => "Cut ✂".to_string(),
+        }
+    }
+
+    /** riapplica il comando, ritorna l'immagine da mostrare se cambia **/
+    fn apply(&self, shapes: &mut Vec<Annotation>) -> Option<RgbaImage> {
+        match self {
+            Command::Add(i, annotation) => shapes.insert(*i, annotation.clone()),
+            Command::Delete(i, _) => { shapes.remove(*i); }
+            Command::Edit(i, _, after, _) => shapes[*i] = after.clone(),
+            Command::Clear(_) => shapes.clear(),
+            Command::Crop { cropped, cropped_shapes, .. } => {
+                *shapes = cropped_shapes.clone();
+                return Some(cropped.clone());
+            }
+        }
+        None
+    }
+
+    /** annulla il comando, ritorna l'immagine da mostrare se cambia **/
+    fn revert(&self, shapes: &mut Vec<Annotation>) -> Option<RgbaImage> {
+        match self {
+            Command::Add(i, _) => { shapes.remove(*i); }
+            Command::Delete(i, annotation) => shapes.insert(*i, annotation.clone()),
+            Command::Edit(i, before, _, _) => shapes[*i] = before.clone(),
+            Command::Clear(old) => *shapes = old.clone(),
+            Command::Crop { image, shapes: old, .. } => {
+                *shapes = old.clone();
+                return Some(image.clone());
+            }
+        }
+        None
+    }
+}
+
+/// Undo/redo stack without a depth limit.
+#[derive(Default)]
+pub struct History {
+    done: Vec<Command>,
+    undone: Vec<Command>,
+    /// the last `Edit` is still being made (a slider held down) and takes the next ones of the same kind
+    editing: bool,
+}
+
+impl History {
+    /** registra un comando giá eseguito, la cronologia di redo viene persa **/
+    pub fn record(&mut self, command: Command) {
+        self.undone.clear();
+        self.done.push(command);
+        self.editing = false;
+    }
+
+    /** come `record`, ma le modifiche dello stesso tipo alla stessa annotazione fatte nella stessa
+    interazione diventano un solo passo (es. trascinare lo slider dello spessore), fino a `end_edit` **/
+    pub fn record_edit(&mut self, i: usize, before: Annotation, after: Annotation, what: &str) {
+        if let (true, Some(Command::Edit(j, _, last, last_what))) = (self.editing, self.done.last_mut()) {
+            if *j == i && *last_what == what {
+                *last = after;
+                return;
+            }
+        }
+        self.record(Command::Edit(i, before, after, what.to_string()));
+        self.editing = true;
+    }
+
+    /** l'interazione é finita (pulsante rilasciato, campo lasciato): la prossima modifica é un altro passo **/
+    pub fn end_edit(&mut self) {
+        self.editing = false;
+    }
+
+    /** cronologia ricostruita da un progetto, `undone` nell'ordine in cui verrebbero rifatti per ultimi **/
+    pub fn from_commands(done: Vec<Command>, undone: Vec<Command>) -> Self {
+        Self { done, undone, editing: false }
+    }
+
+    /** i comandi eseguiti e quelli annullati, come li vuole `from_commands` **/
+    pub fn commands(&self) -> (&[Command], &[Command]) {
+        (&self.done, &self.undone)
+    }
+
+    /** ritorna None se non c'é niente da annullare, altrimenti l'eventuale immagine da ripristinare **/
+    pub fn undo(&mut self, shapes: &mut Vec<Annotation>) -> Option<Option<RgbaImage>> {
+        self.editing = false;
+        let command = self.done.pop()?;
+        let image = command.revert(shapes);
+        self.undone.push(command);
+        Some(image)
+    }
+
+    pub fn redo(&mut self, shapes: &mut Vec<Annotation>) -> Option<Option<RgbaImage>> {
+        self.editing = false;
+        let command = self.undone.pop()?;
+        let image = command.apply(shapes);
+        self.done.push(command);
+        Some(image)
+    }
+
+    /** numero di comandi eseguiti, cioé la posizione corrente nella cronologia **/
+    pub fn position(&self) -> usize {
+        self.done.len()
+    }
+
+    /** tutti i comandi, dal primo all'ultimo, compresi quelli annullati **/
+    pub fn names(&self) -> Vec<String> {
+        self.done.iter().chain(self.undone.iter().rev()).map(Command::name).collect()
+    }
+}
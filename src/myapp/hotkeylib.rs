@@ -1,17 +1,44 @@
-use eframe::Frame;
 use egui::{CentralPanel, Context, Ui, Grid, Color32, Layout, Align};
 use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, hotkey::{Modifiers as KeyModifiers, Code as KeyCode, HotKey}};
-use crate::myapp::{Config, Layouts, KeyModifiersWrapper, KeyCodeWrapper, render_header, PADDING};
-use crate::myapp::imglib::restore_dim;
-use crate::myapp::cutlib::MyCut;
+use crate::myapp::{Config, KeyModifiersWrapper, KeyCodeWrapper, render_header, PADDING};
+use crate::myapp::framelib::AppFrame;
+use crate::myapp::statelib::{AppEvent, AppState};
 use crate::myapp::paintlib::Painting;
 
+/// Where the global hotkeys are registered. The real one is the `GlobalHotKeyManager`,
+/// the headless tests use one that only records the calls.
+pub trait HotKeyRegistry {
+    fn register(&self, hotkey: HotKey) -> global_hotkey::Result<()>;
+    fn unregister(&self, hotkey: HotKey) -> global_hotkey::Result<()>;
+}
+
+impl HotKeyRegistry for GlobalHotKeyManager {
+    fn register(&self, hotkey: HotKey) -> global_hotkey::Result<()> {
+        GlobalHotKeyManager::register(self, hotkey)
+    }
+
+    fn unregister(&self, hotkey: HotKey) -> global_hotkey::Result<()> {
+        GlobalHotKeyManager::unregister(self, hotkey)
+    }
+}
+
 pub enum ChangeState {
     Unregistered,
     Registered,
     Saved,
 }
 
+/// The checks shared by the two hotkey forms while one of them is being changed.
+#[derive(Default)]
+pub struct HotKeyFlags {
+    /// The typed hotkey is already the registered one.
+    pub already_reg: bool,
+    /// The typed hotkey is not used by the other form nor by the standard shortcuts.
+    pub are_different: bool,
+    /// A hotkey is registered and waits to be typed again to be saved.
+    pub saving: bool,
+}
+
 pub struct HotKeyData {
     pub hk: HotKey,
     pub code: KeyCodeWrapper,
@@ -21,8 +48,8 @@ pub struct HotKeyData {
 
 impl HotKeyData {
     /** per cambiare hotkey **/
-    pub fn modify_hk(&mut self, a_reg: &mut bool, diff: &mut bool, manager: &mut GlobalHotKeyManager,
-                     ui: &mut Ui, c: &(u32, String, String), other: &HotKeyData, en: &bool, saving: &mut bool){
+    pub fn modify_hk(&mut self, flags: &mut HotKeyFlags, manager: &dyn HotKeyRegistry,
+                     ui: &mut Ui, c: &(u32, String, String), other: &HotKeyData, en: bool){
         match self.state {
             ChangeState::Registered => {
                 ui.colored_label(Color32::LIGHT_YELLOW,"Type the hotkey again to save it");
@@ -73,7 +100,7 @@ impl HotKeyData {
                             });
                         ui.end_row();
                         ui.label("Change Code");
-                        if *en {
+                        if en {
                             ui.ctx().input(|i| {
                                 if i.keys_down.len() > 0 {
                                     self.code = KeyCodeWrapper::from(i.keys_down.iter().last().unwrap().name().to_string());
@@ -86,19 +113,19 @@ impl HotKeyData {
                         ui.with_layout(Layout::right_to_left(Align::RIGHT), |ui|{
                             if ui.button("Register").clicked() {
                                 if !self.are_hotkeys_valid(other) || is_std_hk(&self.modifiers, &self.code) {
-                                    *diff = false;
+                                    flags.are_different = false;
                                 } else {
                                     //devo unregistrare l'hotkey gia registrata che nel caso della register
                                     //puo essere solo quella delle config, perche se ho fatto annulla rimetto
                                     //quella vecchia, che é sempre una che viene dalle config
-                                    MyHotKey::register(manager, self, &c, a_reg);
-                                    if !*a_reg { self.state = ChangeState::Registered; }
+                                    MyHotKey::register(manager, self, c, &mut flags.already_reg);
+                                    if !flags.already_reg { self.state = ChangeState::Registered; }
                                 }
                             }
                         });
                     }
                     ChangeState::Registered => {
-                        *saving = true;
+                        flags.saving = true;
                         ui.label("The hotkey you have registered is : ");
                         ui.label(format!("{} + {}", self.modifiers.to_string(), self.code.to_string()));
                         ui.end_row();
@@ -116,7 +143,7 @@ impl HotKeyData {
                                 let hotkey = HotKey::new(Some(modifiers.0), code.0);
                                 manager.register(hotkey).expect("Unable to register hotkey");
                                 *self = HotKeyData { hk: hotkey, code, modifiers, state: ChangeState::Unregistered };
-                                *saving = false;
+                                flags.saving = false;
                             }
                         });
                     }
@@ -127,7 +154,7 @@ impl HotKeyData {
                         ui.label("Saved Code:");
                         if ui.button(self.code.to_string()).clicked() {}
                         ui.end_row();
-                        *saving = false;
+                        flags.saving = false;
                         self.reder_progress(ui);
                         ui.with_layout(Layout::right_to_left(Align::RIGHT), |ui| {
                             if ui.button("Edit").clicked() {
@@ -196,20 +223,18 @@ pub enum Radio{
 }
 
 pub struct MyHotKey{
-    manager: GlobalHotKeyManager,
+    manager: Box<dyn HotKeyRegistry>,
     take_screenshot: HotKeyData,
     save_screenshot: HotKeyData,
-    are_different: bool,
-    already_reg: bool,
+    flags: HotKeyFlags,
     radio: Radio,
-    saving: bool,
-    old_ly: Layouts,
+    /// Why the last changed hotkey could not be written to the settings.
+    store_error: Option<String>,
     is_pressed: bool,
 }
 
 impl MyHotKey {
-    pub fn new(take_screenshot: (u32, String, String), save_screenshot: (u32, String, String)) -> Self {
-        let manager = GlobalHotKeyManager::new().unwrap();
+    pub fn new(manager: Box<dyn HotKeyRegistry>, take_screenshot: (u32, String, String), save_screenshot: (u32, String, String)) -> Self {
 
         let modifiers = KeyModifiersWrapper::from(take_screenshot.1);
         let code = KeyCodeWrapper::from(take_screenshot.2);
@@ -226,56 +251,58 @@ impl MyHotKey {
             manager,
             take_screenshot,
             save_screenshot,
-            are_different: true,
-            already_reg: false,
+            flags: HotKeyFlags { are_different: true, ..Default::default() },
             radio: Radio::Take,
-            saving: false,
-            old_ly: Layouts::Home,
+            store_error: None,
             is_pressed: false
         }
     }
 
     /**schermata hotkey**/
-    pub fn hotkey_layout(&mut self, ctx: &Context, _frame: &mut Frame, config: &mut Config, layout: &mut Layouts, dim: &Option<(f32, f32)>){
+    pub fn hotkey_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame, config: &mut Config, state: &mut AppState){
         CentralPanel::default().show(ctx, |ui| {
             render_header(ui, "HOT KEY");
-            self.render_hotkey_body(ui, config, layout, _frame, dim);
+            self.render_hotkey_body(ui, config, state, _frame);
         });
     }
 
     /** per cambiare hotkey **/
-    pub fn render_hotkey_body(&mut self, ui: &mut Ui, config: &mut Config, layout: &mut Layouts, _frame: &mut Frame, dim: &Option<(f32, f32)>) {
+    pub fn render_hotkey_body(&mut self, ui: &mut Ui, config: &mut Config, state: &mut AppState, _frame: &mut dyn AppFrame) {
         ui.horizontal(|ui| {
             ui.with_layout(Layout::top_down(Align::LEFT), |ui|{
-                if !self.are_different {
+                if !self.flags.are_different {
                     ui.colored_label(Color32::LIGHT_RED, "You cannot choose the same hotkey used for other functions");
                     ui.end_row();
                     if self.take_screenshot.are_hotkeys_valid(&self.save_screenshot) {
                         match self.radio {
                             Radio::Take => {
                                 if !is_std_hk(&self.take_screenshot.modifiers, &self.take_screenshot.code) {
-                                    self.are_different = true;
+                                    self.flags.are_different = true;
                                 }
                             }
                             Radio::Save => {
                                 if !is_std_hk(&self.save_screenshot.modifiers, &self.save_screenshot.code) {
-                                    self.are_different = true;
+                                    self.flags.are_different = true;
                                 }
                             }
                         }
                     }
                 }
-                if self.already_reg {
+                if let Some(e) = &self.store_error {
+                    ui.colored_label(Color32::LIGHT_RED, format!("The hotkey works now but could not be saved: {}", e));
+                    ui.end_row();
+                }
+                if self.flags.already_reg {
                     ui.colored_label(Color32::LIGHT_RED, "The hotkey you want to register is already in use");
                     match self.radio {
                         Radio::Take => {
                             if self.take_screenshot.is_already_reg(&config.take_screenshot).is_some() {
-                                self.already_reg = false;
+                                self.flags.already_reg = false;
                             }
                         }
                         Radio::Save => {
                             if self.save_screenshot.is_already_reg(&config.save_screenshot).is_some() {
-                                self.already_reg = false;
+                                self.flags.already_reg = false;
                             }
                         }
                     }
@@ -283,10 +310,9 @@ impl MyHotKey {
                 }
             });
             ui.with_layout(Layout::right_to_left(Align::RIGHT), |ui| {
-                ui.set_enabled(!self.saving);
+                ui.set_enabled(!self.flags.saving);
                 if ui.button("↩").clicked() {
-                    restore_dim(dim, _frame, Some(Layouts::Home));
-                    *layout = Layouts::Home;
+                    state.handle(AppEvent::Back, _frame);
                 }
             });
         });
@@ -324,33 +350,31 @@ impl MyHotKey {
     pub fn render_form(&mut self , ui: &mut Ui, config: &mut Config){
         ui.group(|ui|{
             let enabled = self.radio == Radio::Take;
-            ui.set_enabled(!self.saving || enabled);
+            ui.set_enabled(!self.flags.saving || enabled);
             ui.radio_value(&mut self.radio, Radio::Take, "Take Screenshot");
             ui.end_row();
             ui.group(|ui|{
                 ui.set_enabled(enabled);
-                self.take_screenshot.modify_hk( &mut self.already_reg, &mut self.are_different,
-                                                &mut self.manager, ui, &config.take_screenshot,
-                                                &self.save_screenshot, &enabled, &mut self.saving);
+                self.take_screenshot.modify_hk(&mut self.flags, self.manager.as_ref(), ui,
+                                               &config.take_screenshot, &self.save_screenshot, enabled);
             });
         });
         ui.end_row();
         ui.group(|ui| {
             let enabled = self.radio == Radio::Save;
-            ui.set_enabled(!self.saving || enabled);
+            ui.set_enabled(!self.flags.saving || enabled);
             ui.radio_value(&mut self.radio, Radio::Save, "Save Screenshot").enabled();
             ui.end_row();
             ui.group(|ui| {
                 ui.set_enabled(enabled);
-                self.save_screenshot.modify_hk(&mut self.already_reg, &mut self.are_different,
-                                               &mut self.manager, ui, &config.save_screenshot,
-                                               &self.take_screenshot, &enabled, &mut self.saving);
+                self.save_screenshot.modify_hk(&mut self.flags, self.manager.as_ref(), ui,
+                                               &config.save_screenshot, &self.take_screenshot, enabled);
             });
         });
         ui.end_row();
     }
 
-    pub fn register(manager: &mut GlobalHotKeyManager, hot_key_data: &mut HotKeyData, c: &(u32, String, String), already_reg: &mut bool) {
+    pub fn register(manager: &dyn HotKeyRegistry, hot_key_data: &mut HotKeyData, c: &(u32, String, String), already_reg: &mut bool) {
         if let Some(hk) = hot_key_data.is_already_reg(c) {
             *already_reg = false;   //cosi dico che non é quella vecchia
             let modifiers = KeyModifiersWrapper::from(c.1.to_string());
@@ -369,61 +393,55 @@ impl MyHotKey {
         }
     }
 
-    pub fn match_event(&mut self, event: GlobalHotKeyEvent, _frame: &mut Frame, layout: &mut Layouts, disabled_time: &mut f64,
-                       dim: &Option<(f32, f32)>, is_taken: bool,
-                       ctx: &Context, saving: &mut bool, config: &mut Config, save_by_hk :&mut bool) {
+    /** scrive le hotkey nelle config, l'errore resta a schermo finché un salvataggio non riesce **/
+    fn store(&mut self, config: &Config) {
+        self.store_error = match config.store() {
+            Ok(()) => None,
+            Err(e) => {
+                eprintln!("Unable to save the hotkey: {}", e);
+                Some(e.to_string())
+            }
+        };
+    }
+
+    /** true quando la hotkey di salvataggio é stata accettata e l'immagine va salvata **/
+    pub fn match_event(&mut self, event: GlobalHotKeyEvent, _frame: &mut dyn AppFrame, state: &mut AppState, time: f64,
+                       config: &mut Config) -> bool {
 
         if event.id == self.take_screenshot.hk.id() &&  self.take_screenshot.hk.id() == config.take_screenshot.0 {
-            if *layout != Layouts::Hotkey {
-                match layout {
-                    Layouts::Screenshot => { *layout = self.old_ly },
-                    _ => {
-                        _frame.set_visible(false);
-                        *disabled_time = ctx.input(|i| i.time);
-                        self.old_ly = *layout;
-                        *layout = Layouts::Screenshot;
-                    }
-                }
-                _frame.set_minimized(false);
-                _frame.set_decorations(true);
-                restore_dim(dim, _frame, Some(*layout));
-                _frame.focus();
-            }
+            state.handle(AppEvent::CaptureHotkey { time }, _frame);
         }
         else {
             let enabled = self.radio == Radio::Take;
             if event.id == self.take_screenshot.hk.id() &&  self.take_screenshot.hk.id() != config.take_screenshot.0  && enabled {
                 self.take_screenshot.change_hotkey(&mut config.take_screenshot,
-                                                   &self.save_screenshot, &mut self.are_different);
-                confy::store("screenshot", "screenshot", &config).unwrap();
+                                                   &self.save_screenshot, &mut self.flags.are_different);
+                self.store(config);
                 self.take_screenshot.state = ChangeState::Saved;
             }
             else {
                 if event.id == self.save_screenshot.hk.id() &&  self.save_screenshot.hk.id() == config.save_screenshot.0 {
-                    match layout {
-                        Layouts::Home => {
-                            if is_taken {
-                                *saving = true;
-                                *save_by_hk = true;
-                            }
-                            else { eprintln!("No screenshot taken"); }
-                        },
-                        _ => { }
+                    if state.handle(AppEvent::SaveHotkey, _frame) {
+                        return true;
                     }
+                    eprintln!("Nothing to save now");
                 }
                 else {
                     let enabled = self.radio == Radio::Save;
                     if event.id == self.save_screenshot.hk.id() && self.save_screenshot.hk.id() != config.save_screenshot.0 && enabled {
                         self.save_screenshot.change_hotkey(&mut config.save_screenshot,
-                                                           &self.take_screenshot, &mut self.are_different);
-                        confy::store("screenshot", "screenshot", &config).unwrap();
+                                                           &self.take_screenshot, &mut self.flags.are_different);
+                        self.store(config);
                         self.save_screenshot.state = ChangeState::Saved;
                     }
                 }
             }
         }
+        false
     }
-    pub fn edit_hotkeys(&mut self, ui: &mut Ui, painting: &mut Painting, copy: &mut bool, mycut: &mut Option<MyCut>){
+    /** true quando va copiata l'immagine **/
+    pub fn edit_hotkeys(&mut self, ui: &mut Ui, painting: &mut Painting, state: &mut AppState, _frame: &mut dyn AppFrame) -> bool {
+        let mut copy = false;
         if ui.ctx().input(|i| {i.keys_down.len()>0}) {
             ui.ctx().input(|i| {
                 for (_, keycode) in STD_HOTKEYS.iter() {
@@ -434,7 +452,7 @@ impl MyHotKey {
 
                             match keycode {
                                 KeyCodeWrapper(KeyCode::KeyC) => {
-                                    *copy = true;
+                                    copy = state.handle(AppEvent::Copy, _frame);
                                 },
                                 KeyCodeWrapper(KeyCode::KeyZ) => {
                                     painting.undo();
@@ -446,8 +464,7 @@ impl MyHotKey {
                                     painting.clear();
                                 },
                                 KeyCodeWrapper(KeyCode::KeyT) => {
-                                    //TODO
-                                    *mycut = Some(MyCut::default());
+                                    state.handle(AppEvent::OpenCut, _frame);
                                 },
                                 _ => {}
                             }
@@ -459,6 +476,7 @@ impl MyHotKey {
         else{
             self.is_pressed = false;
         }
+        copy
     }
 }
 
@@ -1,8 +1,6 @@
 use image::{RgbaImage};
 use egui::{ImageData};
 use eframe::epaint::ColorImage;
-use eframe::Frame;
-use crate::myapp::Layouts;
 
 
 #[derive(Debug, PartialEq, Clone)]
@@ -10,7 +8,37 @@ use crate::myapp::Layouts;
 pub enum AllFormats {
     PNG,
     JPEG,
-    GIF
+    GIF,
+    WEBP,
+    BMP,
+    TIFF,
+    QOI,
+    /// the image embedded as PNG, annotations as SVG shapes
+    SVG,
+    /// a printable page, with the caption chosen in the save dialog
+    PDF,
+}
+
+impl AllFormats {
+    /** formati nell'ordine in cui compaiono nei menu **/
+    pub fn all() -> [AllFormats; 9] {
+        [AllFormats::PNG, AllFormats::JPEG, AllFormats::GIF, AllFormats::WEBP, AllFormats::BMP, AllFormats::TIFF, AllFormats::QOI, AllFormats::SVG, AllFormats::PDF]
+    }
+
+    /** None per i formati vettoriali, che l'image crate non scrive **/
+    pub fn image_format(&self) -> Option<image::ImageFormat> {
+        Some(match self {
+            AllFormats::PNG => image::ImageFormat::Png,
+            AllFormats::JPEG => image::ImageFormat::Jpeg,
+            AllFormats::GIF => image::ImageFormat::Gif,
+            //lossless, the encoder of the image crate has no lossy mode
+            AllFormats::WEBP => image::ImageFormat::WebP,
+            AllFormats::BMP => image::ImageFormat::Bmp,
+            AllFormats::TIFF => image::ImageFormat::Tiff,
+            AllFormats::QOI => image::ImageFormat::Qoi,
+            AllFormats::SVG | AllFormats::PDF => return None,
+        })
+    }
 }
 
 impl ToString for AllFormats {
@@ -19,6 +47,12 @@ impl ToString for AllFormats {
             AllFormats::PNG => "png".to_string(),
             AllFormats::JPEG => "jpeg".to_string(),
             AllFormats::GIF => "gif".to_string(),
+            AllFormats::WEBP => "webp".to_string(),
+            AllFormats::BMP => "bmp".to_string(),
+            AllFormats::TIFF => "tiff".to_string(),
+            AllFormats::QOI => "qoi".to_string(),
+            AllFormats::SVG => "svg".to_string(),
+            AllFormats::PDF => "pdf".to_string(),
         }
     }
 }
@@ -66,26 +100,12 @@ pub fn format_from_string(format: &str) -> Option<AllFormats> {
         "png" => Some(AllFormats::PNG),
         "jpeg" => Some(AllFormats::JPEG),
         "gif" => Some(AllFormats::GIF),
+        "webp" => Some(AllFormats::WEBP),
+        "bmp" => Some(AllFormats::BMP),
+        "tiff" => Some(AllFormats::TIFF),
+        "qoi" => Some(AllFormats::QOI),
+        "svg" => Some(AllFormats::SVG),
+        "pdf" => Some(AllFormats::PDF),
         _ => None,
     }
 }
-
-pub fn restore_dim(dim: &Option<(f32, f32)>, frame: &mut Frame, ly: Option<Layouts>) {
-    if ly.is_some(){
-        match ly.unwrap() {
-            Layouts::Home => {
-                println!("{:?}", dim);
-                if let Some((w, h)) = dim {
-                    frame.set_window_size(egui::vec2(*w, *h));
-                }
-                else {
-                    frame.set_window_size(egui::vec2(400., 200.))
-                }
-            },
-            Layouts::Path => { frame.set_window_size(egui::vec2(400., 480.)) },
-            Layouts::Hotkey => { frame.set_window_size(egui::vec2(400., 500.)) },
-            Layouts::About => { frame.set_window_size(egui::vec2(400., 270.)) },
-            _ => {}
-        }
-    }
-}
@@ -1,9 +1,15 @@
+use std::sync::OnceLock;
+use ab_glyph::{Font, FontArc, ScaleFont};
 use egui::*;
 use image::{RgbaImage};
+use serde::{Serialize, Deserialize};
 use crate::myapp::cutlib::MyCut;
+use crate::myapp::historylib::{Annotation, Command, History};
+use crate::myapp::svglib::VectorImage;
 
-#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-#[cfg_attr(feature = "serde", serde(default))]
+/// Serialized in project files: annotations and tool settings, not the editing state.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Painting {
     /// in 0-1 normalized coordinates
     // lines: Option<(Vec<Pos2>, Stroke)> ,
@@ -12,30 +18,141 @@ pub struct Painting {
     //image: Option<Image>,
     // shapes: Vec<Shape>,
     // redo_shapes: Vec<Shape>,
-    shapes: Vec<(Shapes, Vec<Pos2>, Stroke)>,
-    redo_shapes: Vec<(Shapes, Vec<Pos2>, Stroke)>,
+    shapes: Vec<Annotation>,
+    #[serde(skip)]
+    history: History,
+    /// annotation as it was when the current move/resize started
+    #[serde(skip)]
+    edit_before: Option<Annotation>,
+    /// image to show again after undoing or redoing a cut
+    #[serde(skip)]
+    restored_image: Option<RgbaImage>,
     // shape_info: Option<(Pos2, Pos2, Stroke)>,
     shape: Shapes,
+    arrow: ArrowHead,
+    text: TextBox,
+    redaction: Redaction,
+    #[serde(skip)]
+    editing_text: Option<usize>,
+    #[serde(skip)]
+    selected: Option<usize>,
+    #[serde(skip)]
+    select_drag: Option<SelectDrag>,
+    #[serde(skip)]
+    last_pointer: Pos2,
+    #[serde(skip)]
     dim: (Pos2, Pos2),
+    #[serde(skip)]
     response_rect: Rect,
+    #[serde(skip)]
     to_screen: emath::RectTransform,
     //square_proportion: Vec2,
     original_values: Vec2,
+    /// screen points for each pixel of the image, as last shown
+    scale: f32,
+    #[serde(skip)]
     coeff_x: f32,
+    #[serde(skip)]
     coeff_y: f32
 }
-#[derive(PartialEq)]
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub enum Shapes {
     Rect,
     Circle,
+    Arrow(ArrowHead),
+    Text(TextBox),
+    Redact(Redaction),
+    Select,
     None,
 }
 
+#[derive(Clone, Copy)]
+enum SelectDrag {
+    Move,
+    Handle(usize),
+}
+
+/// Distance in screen points within which a click hits a shape or a handle.
+const HIT_TOLERANCE: f32 = 6.;
+const HANDLE_SIZE: f32 = 8.;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct ArrowHead {
+    pub double: bool,
+    pub size: f32,
+}
+
+impl Default for ArrowHead {
+    fn default() -> Self {
+        Self { double: false, size: 15. }
+    }
+}
+
+/// Text annotation, `size` is in pixels of the captured image.
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct TextBox {
+    pub text: String,
+    pub size: f32,
+    pub background: Option<Color32>,
+}
+
+impl Default for TextBox {
+    fn default() -> Self {
+        Self { text: String::new(), size: 32., background: None }
+    }
+}
+
+impl TextBox {
+    /** forma per l'anteprima, `scale` sono i pixel dello schermo per ogni pixel dell'immagine **/
+    pub fn get_shape(&self, fonts: &epaint::Fonts, pos: Pos2, scale: f32, color: Color32) -> Shape {
+        let galley = fonts.layout_no_wrap(self.text.clone(), FontId::proportional(self.size * scale), color);
+        let mut shapes = vec![];
+        if let Some(background) = self.background {
+            let rect = Rect::from_min_size(pos, galley.size()).expand(self.size * TEXT_PADDING * scale);
+            shapes.push(Shape::rect_filled(rect, 0., background));
+        }
+        shapes.push(Shape::galley(pos, galley));
+        Shape::Vec(shapes)
+    }
+}
+
+/// How a redacted area is hidden. Pixels are replaced, so the saved file
+/// does not contain the original content.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Default)]
+pub enum Redaction {
+    #[default]
+    Pixelate,
+    Blur,
+    BlackBox,
+}
+
+impl Redaction {
+    pub fn to_name(self) -> &'static str {
+        match self {
+            Redaction::Pixelate => "Pixelate",
+            Redaction::Blur => "Blur",
+            Redaction::BlackBox => "Black box",
+        }
+    }
+}
+
+/// Blocks along the longer side of a redacted area: fixed size blocks leave a large area readable.
+const REDACT_BLOCKS: u32 = 4;
+/// Smallest side of a block, in image pixels.
+const REDACT_MIN_BLOCK: u32 = 12;
+
+/// Padding of the text background, relative to the font size.
+const TEXT_PADDING: f32 = 0.2;
+
 impl Shapes {
     pub fn to_name(&self) -> &'static str {
         return match self {
             Shapes::Rect => "□",
             Shapes::Circle => "⭕",
+            Shapes::Arrow(_) => "➡",
+            Shapes::Text(_) => "🆃",
+            Shapes::Redact(_) => "▦",
+            Shapes::Select => "⬉",
             Shapes::None => "〰"
         };
     }
@@ -64,6 +181,24 @@ impl Shapes {
                     Shape::Noop
                 }
             }
+            Shapes::Redact(redaction) if pos.len() >= 2 => {
+                //pixels are only available when saving, the preview marks the area
+                let rect = Rect::from_two_pos(pos[0], pos[1]);
+                let fill = match redaction {
+                    Redaction::BlackBox => Color32::BLACK,
+                    _ => Color32::from_black_alpha(180),
+                };
+                Shape::Vec(vec![
+                    Shape::rect_filled(rect, 0., fill),
+                    Shape::rect_stroke(rect, 0., Stroke::new(1., Color32::GRAY)),
+                ])
+            }
+            Shapes::Arrow(head) if pos.len() >= 2 => {
+                Shape::Vec(arrow_segments(pos[0], pos[1], head)
+                    .into_iter()
+                    .map(|segment| Shape::line_segment(segment, stroke))
+                    .collect())
+            }
             _ => { Shape::Noop }
         };
     }
@@ -75,19 +210,19 @@ impl Shapes {
         };
     }
      */
+    /** numero di punti necessari per disegnare la forma **/
+    pub fn min_points(&self) -> usize {
+        match self {
+            Shapes::Text(_) => 1,
+            _ => 2,
+        }
+    }
     pub fn is_none(&self) -> bool {
         return match self {
             Shapes::None => true,
             _ => false
         };
     }
-    pub fn clone(&self) -> Self {
-        return match self {
-            Shapes::Rect => Shapes::Rect,
-            Shapes::Circle => Shapes::Circle,
-            Shapes::None => Shapes::None
-        };
-    }
 }
 
 impl Default for Painting {
@@ -96,14 +231,24 @@ impl Default for Painting {
             stroke: Stroke::new(1.0, Color32::from_rgb(25, 200, 100)),
             //image: None,
             shapes: Default::default(),
-            redo_shapes: Default::default(),
+            history: History::default(),
+            edit_before: None,
+            restored_image: None,
             shape: Shapes::None,
+            arrow: ArrowHead::default(),
+            text: TextBox::default(),
+            redaction: Redaction::default(),
+            editing_text: None,
+            selected: None,
+            select_drag: None,
+            last_pointer: Pos2::ZERO,
             //dim: (Default::default(), 0., 0.),
             dim: (Default::default(), Default::default()),
             response_rect: Rect::NOTHING,
             to_screen: emath::RectTransform::identity(Rect::NOTHING),
             //square_proportion: Vec2::ZERO,
             original_values: Vec2::ZERO,
+            scale: 1.,
             coeff_x: 0.,
             coeff_y: 0.,
         }
@@ -114,32 +259,187 @@ impl Painting {
     // pub fn get_shapes(&self) -> Vec<Shape>  {
     //     self.shapes.clone()
     // }
+    /** annotazioni giá disegnate su un'immagine `size` mostrata a `scale` punti per pixel, senza finestra:
+    i punti sono in pixel dell'immagine, gli spessori in punti dello schermo come nell'editor.
+    Serve ai benchmark e ai test golden, che disegnano senza passare dall'interfaccia **/
+    pub fn with_annotations(size: Vec2, scale: f32, annotations: Vec<Annotation>) -> Self {
+        let to_canvas = emath::RectTransform::from_to(
+            Rect::from_min_size(Pos2::ZERO, size),
+            Rect::from_min_size(Pos2::ZERO, Rect::from_min_size(Pos2::ZERO, size).square_proportions()),
+        );
+        let shapes = annotations
+            .into_iter()
+            .map(|(shape, points, stroke)| (shape, points.into_iter().map(|p| to_canvas * p).collect(), stroke))
+            .collect();
+        Self { shapes, original_values: size, scale, ..Default::default() }
+    }
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+    /** cronologia letta da un progetto, i passi continuano da lí **/
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
     pub fn set_shape(&mut self, shape: Shapes) {
+        self.finish_text();
+        if shape != Shapes::Select {
+            self.selected = None;
+        }
         self.shape = shape;
     }
+    pub fn arrow_head(&self) -> ArrowHead {
+        self.arrow
+    }
+    /** opzioni della punta, valgono per le prossime frecce **/
+    pub fn arrow_options(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.arrow.double, "⬌");
+        ui.add(Slider::new(&mut self.arrow.size, 5.0..=50.0).text("Head"));
+        if let Shapes::Arrow(_) = self.shape {
+            self.shape = Shapes::Arrow(self.arrow);
+        }
+    }
+    pub fn text_box(&self) -> TextBox {
+        self.text.clone()
+    }
+    /** opzioni del testo, valgono per i prossimi riquadri **/
+    pub fn text_options(&mut self, ui: &mut Ui) {
+        ui.add(Slider::new(&mut self.text.size, 8.0..=200.0).text("Size"));
+        let mut background = self.text.background.is_some();
+        if ui.checkbox(&mut background, "Background").changed() {
+            self.text.background = if background { Some(Color32::BLACK) } else { None };
+        }
+        if let Some(color) = self.text.background.as_mut() {
+            color_picker::color_edit_button_srgba(ui, color, color_picker::Alpha::OnlyBlend);
+        }
+        if let Shapes::Text(_) = self.shape {
+            self.shape = Shapes::Text(self.text.clone());
+        }
+    }
+    pub fn redaction(&self) -> Redaction {
+        self.redaction
+    }
+    pub fn redaction_options(&mut self, ui: &mut Ui) {
+        for redaction in [Redaction::Pixelate, Redaction::Blur, Redaction::BlackBox] {
+            ui.selectable_value(&mut self.redaction, redaction, redaction.to_name());
+        }
+        if let Shapes::Redact(_) = self.shape {
+            self.shape = Shapes::Redact(self.redaction);
+        }
+    }
+    /** chiude il riquadro di testo in modifica, se vuoto viene scartato **/
+    fn finish_text(&mut self) {
+        if let Some(i) = self.editing_text.take() {
+            if let Some((Shapes::Text(text), _, _)) = self.shapes.get(i) {
+                if text.text.trim().is_empty() {
+                    self.shapes.remove(i);
+                } else {
+                    self.history.record(Command::Add(i, self.shapes[i].clone()));
+                }
+            }
+        }
+    }
+    /** stile e cancellazione dell'annotazione selezionata **/
+    pub fn selection_options(&mut self, ui: &mut Ui) {
+        let Some(i) = self.selected.filter(|i| *i < self.shapes.len()) else { return };
+        let before = self.shapes[i].clone();
+        let (shape, _, stroke) = &mut self.shapes[i];
+        stroke_ui(ui, stroke, "");
+        if let Shapes::Text(text) = shape {
+            ui.add(Slider::new(&mut text.size, 8.0..=200.0).text("Size"));
+        }
+        if self.shapes[i] != before {
+            self.history.record_edit(i, before, self.shapes[i].clone(), "Style");
+        }
+        //a drag of the slider or the color picker is one step, the next one starts after the release
+        if !ui.input(|i| i.pointer.any_down()) {
+            self.history.end_edit();
+        }
+        if ui.button("🗑").on_hover_text("Delete").clicked() || ui.input(|i| i.key_pressed(Key::Delete)) {
+            self.delete_selected();
+        }
+    }
+    pub fn delete_selected(&mut self) {
+        if let Some(i) = self.selected.take() {
+            let annotation = self.shapes.remove(i);
+            self.history.record(Command::Delete(i, annotation));
+        }
+    }
+    /** forma vuota in fondo alla lista, diventa l'annotazione che si sta disegnando **/
+    fn placeholder(&self) -> Annotation {
+        (self.shape.clone(), vec![], self.stroke)
+    }
+    /** conferma l'annotazione disegnata, se ha abbastanza punti **/
+    fn commit_current(&mut self) {
+        let i = self.shapes.len() - 1;
+        let (shape, line, _) = &self.shapes[i];
+        if line.len() >= shape.min_points() {
+            self.history.record(Command::Add(i, self.shapes[i].clone()));
+            self.shapes.push(self.placeholder());
+        } else {
+            self.shapes[i].1.clear();
+        }
+    }
     pub fn clear(&mut self) {
-        self.shapes.clear();
+        self.finish_text();
+        self.selected = None;
+        self.shapes.pop();
+        if !self.shapes.is_empty() {
+            self.history.record(Command::Clear(std::mem::take(&mut self.shapes)));
+        }
+        self.shapes.push(self.placeholder());
     }
-    pub fn undo(&mut self) {
-        //println!("Shape len {}", self.shapes.len());
-        if self.shapes.len() > 1 {
-            //println!("Inside");
-            let tmp = self.shapes.pop().unwrap();
-            self.redo_shapes.push(self.shapes.pop().unwrap());
-            self.shapes.push(tmp);
+    /** ritorna false se non c'é niente da annullare **/
+    pub fn undo(&mut self) -> bool {
+        self.finish_text();
+        self.selected = None;
+        //the shape being drawn is dropped, commands work on the confirmed ones
+        self.shapes.pop();
+        let undone = self.history.undo(&mut self.shapes);
+        self.shapes.push(self.placeholder());
+        if let Some(Some(image)) = &undone {
+            self.restored_image = Some(image.clone());
         }
+        undone.is_some()
     }
-    pub fn redo(&mut self) {
-        if !self.redo_shapes.is_empty() {
-            let tmp = self.shapes.pop().unwrap();
-            self.shapes.push(self.redo_shapes.pop().unwrap());
-            self.shapes.push(tmp);
+    pub fn redo(&mut self) -> bool {
+        self.finish_text();
+        self.selected = None;
+        self.shapes.pop();
+        let redone = self.history.redo(&mut self.shapes);
+        self.shapes.push(self.placeholder());
+        if let Some(Some(image)) = &redone {
+            self.restored_image = Some(image.clone());
+        }
+        redone.is_some()
+    }
+    /** immagine da mostrare dopo aver annullato o rifatto un taglio **/
+    pub fn take_restored_image(&mut self) -> Option<RgbaImage> {
+        self.restored_image.take()
+    }
+    /** elenco delle modifiche, cliccando una voce si torna a quel punto **/
+    pub fn history_ui(&mut self, ui: &mut Ui) {
+        let position = self.history.position();
+        let mut target = None;
+        ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+            if ui.selectable_label(position == 0, "Start").clicked() {
+                target = Some(0);
+            }
+            for (i, name) in self.history.names().into_iter().enumerate() {
+                let text = if i < position { RichText::new(name) } else { RichText::new(name).weak() };
+                if ui.selectable_label(position == i + 1, text).clicked() {
+                    target = Some(i + 1);
+                }
+            }
+        });
+        if let Some(target) = target {
+            while self.history.position() > target && self.undo() {}
+            while self.history.position() < target && self.redo() {}
         }
     }
     pub fn stroke(&mut self, ui: &mut Ui) {
         stroke_ui(ui, &mut self.stroke, "");
     }
-    pub fn ui_content(&mut self, ui: &mut Ui, texture: &&TextureHandle, mycut: &mut Option<MyCut>) -> egui::Response {
+    pub fn ui_content(&mut self, ui: &mut Ui, texture: &&TextureHandle, mycut: Option<&mut MyCut>) -> egui::Response {
         let size = ui.available_size_before_wrap() * 0.93;
         let mut image_width = texture.size_vec2().x;
         let mut image_height = texture.size_vec2().y;
@@ -154,6 +454,8 @@ impl Painting {
         }
         let (mut response, painter) =
             ui.allocate_painter(Vec2::new(image_width, image_height), Sense::drag());
+        //named for screen readers, the headless tests find the image the same way
+        response.widget_info(|| WidgetInfo::labeled(WidgetType::Other, "Screenshot"));
 
         if self.original_values == Vec2::ZERO {
             self.original_values = texture.size_vec2();
@@ -192,12 +494,29 @@ impl Painting {
             Color32::WHITE)
         );
         self.dim = (response.rect.min, response.rect.max);
+        //screen points for each pixel of the image
+        let scale = image_width / texture.size_vec2().x;
+        self.scale = scale;
 
         if mycut.is_none() {
             if self.shapes.is_empty() {
                 self.shapes.push((self.shape.clone(), vec![], self.stroke.clone()));
             }
-            if self.shape.is_none() {
+            if let Shapes::Text(_) = self.shape {
+                if response.drag_started() && self.editing_text.is_none() {
+                    if let Some(pointer_pos) = response.interact_pointer_pos() {
+                        //the placeholder becomes the new text box
+                        *self.shapes.last_mut().unwrap() = (self.shape.clone(), vec![from_screen * pointer_pos], self.stroke);
+                        self.editing_text = Some(self.shapes.len() - 1);
+                        self.shapes.push(self.placeholder());
+                        response.mark_changed();
+                    }
+                } else if response.drag_started() {
+                    self.finish_text();
+                }
+            } else if let Shapes::Select = self.shape {
+                self.select_interaction(ui, &response, to_screen, scale);
+            } else if self.shape.is_none() {
                 let (current_shape, current_line, current_color) = self.shapes.last_mut().unwrap();
                 if let Some(pointer_pos) = response.interact_pointer_pos() {
                     let canvas_pos = from_screen * pointer_pos;
@@ -209,8 +528,7 @@ impl Painting {
                         response.mark_changed();
                     }
                 } else if !current_line.is_empty() {
-                    self.redo_shapes.clear();
-                    self.shapes.push((self.shape.clone(), vec![], self.stroke.clone()));
+                    self.commit_current();
                     response.mark_changed();
                 }
             } else {
@@ -229,8 +547,7 @@ impl Painting {
                         response.mark_changed();
                     }
                 } else if !current_line.is_empty() {
-                    self.redo_shapes.clear();
-                    self.shapes.push((self.shape.clone(), vec![], self.stroke.clone()));
+                    self.commit_current();
                     response.mark_changed();
                 }
             }
@@ -239,7 +556,7 @@ impl Painting {
         let shapes = self
             .shapes
             .iter()
-            .filter(|(_, line, _)| line.len() >= 2)
+            .filter(|(shape, line, _)| line.len() >= shape.min_points())
             .map(|(shape, line, stroke)| {
                 match shape {
                     Shapes::None => {
@@ -257,18 +574,172 @@ impl Painting {
                         let points: Vec<Pos2> = line.iter().map(|p| to_screen * *p).collect();
                         return shape.get_shape(points, *stroke);
                     }
+                    Shapes::Arrow(_) => {
+                        let points: Vec<Pos2> = line.iter().map(|p| to_screen * *p).collect();
+                        shape.get_shape(points, *stroke)
+                    }
+                    Shapes::Text(text) => {
+                        ui.fonts(|fonts| text.get_shape(fonts, to_screen * line[0], scale, stroke.color))
+                    }
+                    Shapes::Redact(_) => {
+                        let points: Vec<Pos2> = line.iter().map(|p| to_screen * *p).collect();
+                        shape.get_shape(points, *stroke)
+                    }
+                    Shapes::Select => Shape::Noop,
                 }
-            });
+            }).collect::<Vec<_>>();
 
         //let shapes = shapes.chain(self.shapes.clone());
         //self.image = Some(create_image_buffer(self.lines.clone(), &mut mesh.clone()));
         painter.extend(shapes);
-        if mycut.is_some() {
-            mycut.as_mut().unwrap().select_cut_rectangle(ui, response.clone(), Vec2::new(image_width, image_height));
+        self.text_edit(ui, to_screen, scale);
+        if mycut.is_none() {
+            self.selection_overlay(ui, &painter, to_screen, scale);
+        }
+        if let Some(mycut) = mycut {
+            mycut.select_cut_rectangle(ui, response.clone(), Vec2::new(image_width, image_height));
         }
 
         response
     }
+    fn select_interaction(&mut self, ui: &Ui, response: &Response, to_screen: emath::RectTransform, scale: f32) {
+        let from_screen = to_screen.inverse();
+        let Some(pointer) = response.interact_pointer_pos() else {
+            if let (Some(drag), Some(before), Some(i)) = (self.select_drag.take(), self.edit_before.take(), self.selected) {
+                if self.shapes[i] != before {
+                    let what = match drag { SelectDrag::Move => "Move", SelectDrag::Handle(_) => "Resize" };
+                    self.history.record(Command::Edit(i, before, self.shapes[i].clone(), what.to_string()));
+                }
+            }
+            return;
+        };
+        if response.drag_started() {
+            let handle = self.selected.and_then(|i| {
+                self.handles(i, to_screen).iter().position(|h| h.distance(pointer) <= HANDLE_SIZE)
+            });
+            self.select_drag = match handle {
+                Some(h) => Some(SelectDrag::Handle(h)),
+                None => {
+                    self.selected = self.hit_test(ui, pointer, to_screen, scale);
+                    self.selected.map(|_| SelectDrag::Move)
+                }
+            };
+            self.edit_before = self.selected.map(|i| self.shapes[i].clone());
+        } else if let (Some(i), Some(drag)) = (self.selected, self.select_drag) {
+            let target = from_screen * pointer;
+            let delta = target - from_screen * self.last_pointer;
+            if let Some((shape, line, _)) = self.shapes.get_mut(i) {
+                match (drag, shape) {
+                    (SelectDrag::Move, _) => line.iter_mut().for_each(|p| *p += delta),
+                    (SelectDrag::Handle(_), Shapes::None) => {
+                        //freehand lines are scaled from the top left corner
+                        let bounds = Rect::from_points(line);
+                        let sx = if bounds.width() > 0. { (target.x - bounds.min.x) / bounds.width() } else { 1. };
+                        let sy = if bounds.height() > 0. { (target.y - bounds.min.y) / bounds.height() } else { 1. };
+                        if sx > 0. && sy > 0. {
+                            line.iter_mut().for_each(|p| *p = bounds.min + (*p - bounds.min) * vec2(sx, sy));
+                        }
+                    }
+                    (SelectDrag::Handle(_), Shapes::Circle) => line[1] = target,
+                    (SelectDrag::Handle(h), _) => line[h] = target,
+                }
+            }
+        }
+        self.last_pointer = pointer;
+    }
+
+    /** indice dell'annotazione piú in alto sotto il puntatore **/
+    fn hit_test(&self, ui: &Ui, pos: Pos2, to_screen: emath::RectTransform, scale: f32) -> Option<usize> {
+        self.shapes
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, (shape, line, _))| line.len() >= shape.min_points())
+            .find(|(i, (shape, line, stroke))| {
+                let points: Vec<Pos2> = line.iter().map(|p| to_screen * *p).collect();
+                let tolerance = HIT_TOLERANCE + stroke.width / 2.;
+                match shape {
+                    Shapes::Rect => {
+                        let rect = Rect::from_two_pos(points[0], points[1]);
+                        rect.expand(tolerance).contains(pos) && !rect.shrink(tolerance).contains(pos)
+                    }
+                    Shapes::Circle => (points[0].distance(pos) - points[0].distance(points[1])).abs() <= tolerance,
+                    Shapes::Arrow(_) | Shapes::None => {
+                        points.windows(2).any(|w| distance_to_segment(pos, w[0], w[1]) <= tolerance)
+                    }
+                    Shapes::Text(_) | Shapes::Redact(_) => self.bounds(ui, *i, to_screen, scale).contains(pos),
+                    Shapes::Select => false,
+                }
+            })
+            .map(|(i, _)| i)
+    }
+
+    /** rettangolo che contiene l'annotazione, in coordinate dello schermo **/
+    fn bounds(&self, ui: &Ui, i: usize, to_screen: emath::RectTransform, scale: f32) -> Rect {
+        let (shape, line, _) = &self.shapes[i];
+        let points: Vec<Pos2> = line.iter().map(|p| to_screen * *p).collect();
+        match shape {
+            Shapes::Circle => Rect::from_center_size(points[0], Vec2::splat(2. * points[0].distance(points[1]))),
+            Shapes::Text(text) => {
+                let size = ui.fonts(|f| {
+                    f.layout_no_wrap(text.text.clone(), FontId::proportional(text.size * scale), Color32::WHITE).size()
+                });
+                Rect::from_min_size(points[0], size).expand(text.size * TEXT_PADDING * scale)
+            }
+            _ => Rect::from_points(&points),
+        }
+    }
+
+    /** maniglie per ridimensionare l'annotazione, in coordinate dello schermo **/
+    fn handles(&self, i: usize, to_screen: emath::RectTransform) -> Vec<Pos2> {
+        let Some((shape, line, _)) = self.shapes.get(i) else { return vec![] };
+        match shape {
+            Shapes::Rect | Shapes::Redact(_) | Shapes::Arrow(_) => vec![to_screen * line[0], to_screen * line[1]],
+            Shapes::Circle => vec![to_screen * line[1]],
+            Shapes::None => vec![to_screen * Rect::from_points(line).max],
+            _ => vec![],
+        }
+    }
+
+    fn selection_overlay(&self, ui: &Ui, painter: &Painter, to_screen: emath::RectTransform, scale: f32) {
+        let Some(i) = self.selected.filter(|i| *i < self.shapes.len()) else { return };
+        let rect = self.bounds(ui, i, to_screen, scale).expand(HIT_TOLERANCE / 2.);
+        painter.extend(Shape::dashed_line(
+            &[rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom(), rect.left_top()],
+            Stroke::new(1., Color32::WHITE), 4., 4.));
+        for handle in self.handles(i, to_screen) {
+            painter.rect(Rect::from_center_size(handle, Vec2::splat(HANDLE_SIZE)), 0., Color32::WHITE, Stroke::new(1., Color32::BLACK));
+        }
+    }
+
+    /** campo di testo sopra il riquadro in modifica: il testo é disegnato dall'anteprima,
+    qui restano visibili solo cursore e selezione **/
+    fn text_edit(&mut self, ui: &mut Ui, to_screen: emath::RectTransform, scale: f32) {
+        let Some(i) = self.editing_text else { return };
+        let Some((Shapes::Text(text), line, _)) = self.shapes.get_mut(i) else {
+            self.editing_text = None;
+            return;
+        };
+        let output = Area::new("text_annotation")
+            .fixed_pos(to_screen * line[0])
+            .order(Order::Foreground)
+            .show(ui.ctx(), |ui| {
+                TextEdit::multiline(&mut text.text)
+                    .font(FontId::proportional(text.size * scale))
+                    .text_color(Color32::TRANSPARENT)
+                    .frame(false)
+                    .margin(Vec2::ZERO)
+                    .desired_rows(1)
+                    .desired_width(f32::INFINITY)
+                    .show(ui)
+            }).inner;
+        if !output.response.has_focus() {
+            output.response.request_focus();
+        }
+        if ui.input(|i| i.key_pressed(Key::Escape)) {
+            self.finish_text();
+        }
+    }
     /*
     pub fn create_rgba(&mut self, ctx: &Context, _frame: &mut eframe::Frame) -> Option<(Vec<u8>, u32, u32)> {
         ctx.set_cursor_icon(CursorIcon::None);
@@ -283,148 +754,64 @@ impl Painting {
     }
      */
     pub fn edit_rgba(&mut self, mut img: RgbaImage) -> Option<(Vec<u8>, u32, u32)> {
-        // let pixels = img.enumerate_pixels_mut();
-        // let pixel = img.get_pixel(0, 0);
-
+        for annotation in self.image_annotations() {
+            draw_annotation(&mut img, &annotation);
+        }
+        Some((img.to_vec(), img.width(), img.height()))
+    }
 
-        //let to_img = self.to_screen;
-        //println!("response_min: {:?}, response_max: {:?}", response.rect.min, response.rect.max);
-        let width = img.width();
-        let height = img.height();
+    /** annotazioni complete in pixel dell'immagine, con spessori e punte giá convertiti:
+    nell'anteprima sono in punti dello schermo **/
+    pub fn image_annotations(&self) -> Vec<Annotation> {
         let to_img = emath::RectTransform::from_to(
             Rect::from_min_size(Pos2::ZERO, Rect::from_min_size(Pos2::ZERO, self.original_values).square_proportions()),
             Rect::from_min_size(Pos2::ZERO, self.original_values),
         );
-        //println!("where: {:?}, {:?}", self.dim.0, self.dim.1);
+        let scale = self.scale;
         self.shapes
             .iter()
-            .filter(|(_, line, _)| line.len() >= 2)
-            .for_each(|(shape, line, stroke)| {
-                //println!("Color: {:?}, line: {}",  stroke.color, line.len());
-                match shape {
-                    Shapes::None => {
-                        let points: Vec<Pos2> = line.iter().map(|p| to_img * *p).collect();
-                        //println!("Points: {:?}", points.len());
-                        for i in 0..points.len() - 1 {
-                            let start = points[i];
-                            let end = points[i + 1];
-                            //println!("Getting pixels {}, start: {:?}, end: {:?}", i, start, end);
-                            let pixels = calc_pixels_rect(start, end, stroke.width);
-
-                                let alpha = stroke.color[3] as f32 / 255.0; // Alpha value in the range [0, 1]
-                                let color = image::Rgba(stroke.color.to_array());
-                                for p in pixels {//println!("p.x {}, p.y {}, dim {}, ppp {}", p.x, p.y, img.width(), ctx.pixels_per_point());
-                                    if ((p.x) as u32) < width && ((p.y) as u32) < height && p.x >= 0. && p.y >= 0. {
-                                        let pixel = img.get_pixel_mut((p.x) as u32, (p.y) as u32);
-                                        // For example, you can set the pixel to red
-
-                                        *pixel = image::Rgba([
-                                            (pixel[0] as f32 * (1.0 - alpha) + color[0] as f32 * alpha) as u8,
-                                            (pixel[1] as f32 * (1.0 - alpha) + color[1] as f32 * alpha) as u8,
-                                            (pixel[2] as f32 * (1.0 - alpha) + color[2] as f32 * alpha) as u8,
-                                            255
-                                        ]);
-                                    }
-                                }
-                        }
-                    }
-                    Shapes::Rect => {
-                        let mut points = Vec::new();
-                        points.push(to_img * line[0]);
-                        points.push(to_img * Pos2::new(line[0].x, line[1].y));
-                        points.push(to_img * line[1]);
-                        points.push(to_img * Pos2::new(line[1].x, line[0].y));
-                        points.push(to_img * line[0]);
-                        //points = line.iter().map(|p| { to_img * p; println!("p.x {}, p.x - x {}", p.x, p.x - x); Pos2::new(p.x - x, p.y - y) }).collect();
-                        for i in 0..points.len() - 1 {
-                            let start = points[i];
-                            let end = points[i + 1];
-                            //println!("img.width {}", width);
-                            let pixels = calc_pixels_rect(start, end, stroke.width);
-                            let alpha = stroke.color[3] as f32 / 255.0; // Alpha value in the range [0, 1]
-                            let color = image::Rgba(stroke.color.to_array());
-                            for p in pixels {
-                                //println!("p.x {}, p.y {}, dim {}, ppp {}", p.x, p.y, img.width(), ctx.pixels_per_point());
-                                //if p.x as u32 <= img.width() && p.y as u32 <= img.height() {
-                                //println!("p.x - x {}, p.x - x {}", p.x - x, (p.x - x) as u32);
-                                if ((p.x) as u32) < width && ((p.y) as u32) < height && p.x >= 0. && p.y >= 0. {
-                                    let pixel = img.get_pixel_mut((p.x) as u32, (p.y) as u32);
-
-                                    *pixel = image::Rgba([
-                                        (pixel[0] as f32 * (1.0 - alpha) + color[0] as f32 * alpha) as u8,
-                                        (pixel[1] as f32 * (1.0 - alpha) + color[1] as f32 * alpha) as u8,
-                                        (pixel[2] as f32 * (1.0 - alpha) + color[2] as f32 * alpha) as u8,
-                                        255
-                                    ]);
-                                }
-                               //}
-
-                            }
-                        }
-                    }
-                    Shapes::Circle => {
-                        //println!("Circle");
-                        let center = to_img * line[0];
-                        let point = to_img * line[1];
-                        let y_c = circle_equation(center/*, point*/);
-                        let rad = distance(center - point);
-                        let alpha = stroke.color[3] as f32 / 255.0; // Alpha value in the range [0, 1]
-                        let color = image::Rgba(stroke.color.to_array());
-                        let thickness = (stroke.width / 2.) as i32;
-                        //println!("x_min: {} - {}, point: {}", center.x, rad, point.x);
-
-
-                        // Increase the number of iterations for better precision
-                        let num_iterations = 1000 * rad as i32;
-                        let step_size = rad * 2.0 / num_iterations as f32;
-
-                        for i in 0..=num_iterations {
-                            let x_c = center.x - rad + (i as f32) * step_size;
-                            let my_y = y_c(x_c as f32, rad);
-                            let my_y_2 = center.y - (my_y - center.y);
-                            //println!("my_y: {}, x: {}", my_y, x_c);
-
-                            if !my_y.is_nan() {
-                                for dx in (-thickness)..=thickness {
-                                    for dy in (-thickness)..=thickness {
-                                        if ((x_c as i32 + dx) as u32) < width && ((my_y as i32 + dy) as u32) < height && (x_c as i32 + dx) >= 0  && (my_y as i32 + dy) >= 0 {
-                                            let pixel = img.get_pixel_mut((x_c + dx as f32) as u32, (my_y + dy as f32) as u32);
-
-                                            *pixel = image::Rgba([
-                                                (pixel[0] as f32 * (1.0 - alpha) + color[0] as f32 * alpha) as u8,
-                                                (pixel[1] as f32 * (1.0 - alpha) + color[1] as f32 * alpha) as u8,
-                                                (pixel[2] as f32 * (1.0 - alpha) + color[2] as f32 * alpha) as u8,
-                                                255
-                                            ]);
-                                        }
-                                    }
-                                }
-                            }
-
-                            if !my_y_2.is_nan() {
-                                for dx in (-thickness)..=thickness {
-                                    for dy in (-thickness)..=thickness {
-                                        if ((x_c as i32 + dx) as u32) < width && ((my_y_2 as i32 + dy) as u32) < height && ((x_c as i32 + dx)) >= 0 && (my_y as i32 + dy) >= 0 {
-                                            let pixel = img.get_pixel_mut((x_c + dx as f32) as u32, (my_y_2 + dy as f32) as u32);
-
-                                            *pixel = image::Rgba([
-                                                (pixel[0] as f32 * (1.0 - alpha) + color[0] as f32 * alpha) as u8,
-                                                (pixel[1] as f32 * (1.0 - alpha) + color[1] as f32 * alpha) as u8,
-                                                (pixel[2] as f32 * (1.0 - alpha) + color[2] as f32 * alpha) as u8,
-                                                255
-                                            ]);
-                                        }
-                                    }
-                                }
-                            }
-                        }
+            .filter(|(shape, line, _)| line.len() >= shape.min_points())
+            .map(|(shape, line, stroke)| {
+                let mut points: Vec<Pos2> = line.iter().map(|p| to_img * *p).collect();
+                let shape = match shape {
+                    Shapes::Arrow(head) => Shapes::Arrow(ArrowHead { size: head.size / scale, ..*head }),
+                    Shapes::Circle if points[0] == points[1] => {
+                        points[1].x += 10. / scale;
+                        Shapes::Circle
                     }
-                }
+                    shape => shape.clone(),
+                };
+                (shape, points, Stroke::new(stroke.width / scale, stroke.color))
+            })
+            .collect()
+    }
+
+    /** immagine con le oscurazioni applicate e le altre annotazioni da disegnare sopra, per i formati vettoriali.
+    Quello che un'oscurazione successiva copre viene disegnato nella bitmap prima di oscurarla,
+    altrimenti resterebbe leggibile nel file **/
+    pub fn vector_image(&self, mut img: RgbaImage) -> VectorImage {
+        let all = self.image_annotations();
+        let mut annotations = vec![];
+        for (i, annotation) in all.iter().enumerate() {
+            let bounds = annotation_bounds(annotation);
+            let covered = all[i + 1..].iter().any(|(shape, points, _)| {
+                matches!(shape, Shapes::Redact(_)) && Rect::from_two_pos(points[0], points[1]).intersects(bounds)
             });
-        return Some((img.to_vec(), img.width(), img.height()));
+            match annotation.0 {
+                Shapes::Redact(_) => draw_annotation(&mut img, annotation),
+                _ if covered => draw_annotation(&mut img, annotation),
+                _ => annotations.push(annotation.clone()),
+            }
+        }
+        VectorImage { background: img, annotations }
     }
 
-    pub fn adapt_to_cut(&mut self, cutrect: Rect) {
+    /** sposta le annotazioni nell'immagine tagliata, `image` e `cropped` servono per annullare il taglio **/
+    pub fn adapt_to_cut(&mut self, cutrect: Rect, image: RgbaImage, cropped: RgbaImage) {
+        self.finish_text();
+        self.selected = None;
+        self.shapes.pop();
+        let shapes = self.shapes.clone();
 
         for (_, line, _) in &mut self.shapes {
             let points: Vec<Pos2> = line.iter_mut().map(|p| {
@@ -444,162 +831,280 @@ impl Painting {
 
             *line = points;
         }
+        self.history.record(Command::Crop { image, cropped, shapes, cropped_shapes: self.shapes.clone() });
+        self.shapes.push(self.placeholder());
     }
 }
 
 
 
-pub fn calc_pixels(start: Pos2, end: Pos2, thickness: f32) -> Vec<Pos2> {
-    let mut pixels = Vec::new();
-
-    // // Calculate the direction vector of the line segment
-    // let direction = end - start;
-    //
-    // // Calculate the length of the direction vector
-    // let length = direction.length();
-    //
-    // // Normalize the direction vector
-    // let normalized_direction = direction / length;
-    //
-    // // Calculate the perpendicular vector to the direction vector
-    // let perpendicular = egui::Pos2::new(-normalized_direction.y, normalized_direction.x);
-    //
-    // // Calculate the offset from the line based on thickness
-    // let offset = vec2(perpendicular.x * (thickness / 2.0), perpendicular.y * (thickness / 2.0));
-
-    let delta_x = (end.x - start.x).abs();
-    let delta_y = (end.y - start.y).abs();
-
-    let step_x = if start.x < end.x { 1.0 } else { -1.0 };
-    let step_y = if start.y < end.y { 1.0 } else { -1.0 };
+/** segmenti che compongono la freccia: asta e una o due punte **/
+pub fn arrow_segments(start: Pos2, end: Pos2, head: &ArrowHead) -> Vec<[Pos2; 2]> {
+    let mut segments = vec![[start, end]];
+    let direction = (end - start).normalized();
+    if direction.x.is_nan() || direction.y.is_nan() {
+        return segments;
+    }
+    let tips = if head.double { vec![(end, direction), (start, -direction)] } else { vec![(end, direction)] };
+    for (tip, dir) in tips {
+        for angle in [-0.45f32, 0.45] {
+            let back = emath::Rot2::from_angle(angle) * -dir;
+            segments.push([tip, tip + back * head.size]);
+        }
+    }
+    segments
+}
 
-    let mut x = start.x;
-    let mut y = start.y;
+fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_sq() > 0. { ((p - a).dot(ab) / ab.length_sq()).clamp(0., 1.) } else { 0. };
+    p.distance(a + ab * t)
+}
 
-    let mut error = delta_x - delta_y;
-    //println!("start {:?}, end {:?}", start, end);
-    pixels.push(Pos2::new(x, y));
+/** disegna un'annotazione, in pixel dell'immagine **/
+fn draw_annotation(img: &mut RgbaImage, (shape, points, stroke): &Annotation) {
+    match shape {
+        Shapes::None => {
+            stroke_path(img, polyline(std::slice::from_ref(points), false), stroke.width, stroke.color);
+        }
+        Shapes::Rect => {
+            let corners = vec![points[0], pos2(points[0].x, points[1].y), points[1], pos2(points[1].x, points[0].y)];
+            stroke_path(img, polyline(&[corners], true), stroke.width, stroke.color);
+        }
+        Shapes::Circle => {
+            let circle = tiny_skia::PathBuilder::from_circle(points[0].x, points[0].y, points[0].distance(points[1]));
+            stroke_path(img, circle, stroke.width, stroke.color);
+        }
+        Shapes::Arrow(head) => {
+            let segments: Vec<Vec<Pos2>> = arrow_segments(points[0], points[1], head)
+                .into_iter()
+                .map(Vec::from)
+                .collect();
+            stroke_path(img, polyline(&segments, false), stroke.width, stroke.color);
+        }
+        Shapes::Text(text) => {
+            draw_text(img, points[0], text, stroke.color);
+        }
+        Shapes::Redact(redaction) => {
+            redact(img, Rect::from_two_pos(points[0], points[1]), redaction);
+        }
+        Shapes::Select => {}
+    }
+}
 
-    while (end.x - x).abs() > 0.7 || (end.y - y).abs() > 0.7 {
-        //println!("point: {} {}", x, y);
-        let double_error = error * 2.0;
+/** area che l'annotazione puó toccare, spessore e spigoli compresi **/
+fn annotation_bounds((shape, points, stroke): &Annotation) -> Rect {
+    //a miter reaches up to 4 half widths from the corner, tiny-skia's default limit
+    let margin = stroke.width * 2. + 1.;
+    match shape {
+        Shapes::None | Shapes::Rect => Rect::from_points(points).expand(margin),
+        Shapes::Circle => Rect::from_center_size(points[0], Vec2::splat(2. * points[0].distance(points[1]))).expand(margin),
+        Shapes::Arrow(head) => {
+            let ends: Vec<Pos2> = arrow_segments(points[0], points[1], head).into_iter().flatten().collect();
+            Rect::from_points(&ends).expand(margin)
+        }
+        //glyphs can stick out of their advance a little
+        Shapes::Text(text) => text_layout(points[0], text).background.expand(text.size * 0.25),
+        Shapes::Redact(_) | Shapes::Select => Rect::from_two_pos(points[0], points[1]),
+    }
+}
 
-        if double_error > -delta_y {
-            error -= delta_y;
-            x += step_x;
+/** percorso fatto da una o piú spezzate **/
+fn polyline(lines: &[Vec<Pos2>], closed: bool) -> Option<tiny_skia::Path> {
+    let mut builder = tiny_skia::PathBuilder::new();
+    for line in lines.iter().filter(|line| !line.is_empty()) {
+        builder.move_to(line[0].x, line[0].y);
+        line[1..].iter().for_each(|p| builder.line_to(p.x, p.y));
+        if closed {
+            builder.close();
         }
+    }
+    builder.finish()
+}
 
-        if double_error < delta_x {
-            error += delta_x;
-            y += step_y;
+/** disegna il contorno con antialiasing: estremi piatti e giunzioni a spigolo come in egui **/
+fn stroke_path(img: &mut RgbaImage, path: Option<tiny_skia::Path>, width: f32, color: Color32) {
+    let Some(path) = path else { return };
+    if width <= 0. {
+        return;
+    }
+    let (w, h) = img.dimensions();
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut paint = tiny_skia::Paint::default();
+    paint.set_color_rgba8(r, g, b, a);
+    paint.anti_alias = true;
+    let stroke = tiny_skia::Stroke { width, ..Default::default() };
+    //tiny-skia wants premultiplied alpha and the image is straight: where the stroke can land,
+    //pixels that are not opaque (an imported PNG, the transparent corners of a composite) are
+    //converted before drawing and back after
+    let area = path.clone().stroke(&stroke, 1.).and_then(|outline| pixel_area(outline.bounds(), w, h));
+    let translucent = area.filter(|&area| pixels_in(area).any(|(x, y)| img.get_pixel(x, y).0[3] < 255));
+    if let Some(area) = translucent {
+        for (x, y) in pixels_in(area) {
+            let pixel = img.get_pixel_mut(x, y);
+            let [r, g, b, a] = pixel.0;
+            let premultiplied = tiny_skia::ColorU8::from_rgba(r, g, b, a).premultiply();
+            pixel.0 = [premultiplied.red(), premultiplied.green(), premultiplied.blue(), premultiplied.alpha()];
         }
-        // let num_pixels = (length / thickness).ceil() as usize;
-        // for i in 0..num_pixels {
-        //     let t = i as f32 / (num_pixels - 1) as f32; // Spread the pixels along the line
-        //     let pixel_pos = start + normalized_direction * (length * t) - offset;
-        //     pixels.push(pixel_pos);
-        // }
-        let num_iterations = (thickness * 2.) + 1.;
-        let step_size = thickness / num_iterations;
-
-        //println!("{}", step_size);
-
-        for dx in 0..num_iterations as i32 {
-            for dy in 0..num_iterations as i32 {
-                let offset_x = step_size * dx as f32;
-                let offset_y = step_size * dy as f32;
-                let mut new_x = x + offset_x as f32;
-                let mut new_y = y + offset_y as f32;
-                //println!("{} {}", new_x, new_y);
-                pixels.push(Pos2::new(new_x, new_y));
-                new_x = x - offset_x as f32;
-                new_y = y - offset_y as f32;
-                pixels.push(Pos2::new(new_x, new_y));
+    }
+    if let Some(mut pixmap) = tiny_skia::PixmapMut::from_bytes(img.as_mut(), w, h) {
+        pixmap.stroke_path(&path, &paint, &stroke, tiny_skia::Transform::identity(), None);
+    }
+    if let Some(area) = translucent {
+        for (x, y) in pixels_in(area) {
+            let pixel = img.get_pixel_mut(x, y);
+            let [r, g, b, a] = pixel.0;
+            //drawing keeps the channels within alpha, so the bytes are a valid premultiplied color
+            let straight = tiny_skia::PremultipliedColorU8::from_rgba(r, g, b, a).map(|c| c.demultiply());
+            if let Some(c) = straight {
+                pixel.0 = [c.red(), c.green(), c.blue(), c.alpha()];
             }
         }
-
-        pixels.push(Pos2::new(x, y));
     }
-
-    pixels
 }
-pub fn calc_pixels_rect(start: Pos2, end: Pos2, thickness: f32) -> Vec<Pos2>  {
-    let mut pixels = Vec::new();
-    let delta_x = (end.x - start.x).abs();
-    let delta_y = (end.y - start.y).abs();
 
-    let step_x = if start.x < end.x { 1.0 } else { -1.0 };
-    let step_y = if start.y < end.y { 1.0 } else { -1.0 };
+/** i pixel coperti da `bounds` piú uno per l'antialiasing, dentro all'immagine: (x0, y0, x1, y1) esclusi x1 e y1 **/
+fn pixel_area(bounds: tiny_skia::Rect, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let x0 = (bounds.left().floor() - 1.).max(0.) as u32;
+    let y0 = (bounds.top().floor() - 1.).max(0.) as u32;
+    let x1 = ((bounds.right().ceil() + 1.).max(0.) as u32).min(width);
+    let y1 = ((bounds.bottom().ceil() + 1.).max(0.) as u32).min(height);
+    (x0 < x1 && y0 < y1).then_some((x0, y0, x1, y1))
+}
 
-    let mut x = start.x;
-    let mut y = start.y;
+fn pixels_in((x0, y0, x1, y1): (u32, u32, u32, u32)) -> impl Iterator<Item = (u32, u32)> {
+    (y0..y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+}
 
-    let mut error = delta_x - delta_y;
-    //println!("start {:?}, end {:?}", start, end);
-    pixels.push(Pos2::new(x, y));
+/** font proporzionale di egui, cosí il testo salvato é uguale all'anteprima **/
+fn text_font() -> &'static FontArc {
+    static FONT: OnceLock<FontArc> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let data = FontDefinitions::default().font_data.remove("Ubuntu-Light").expect("Missing bundled font");
+        FontArc::try_from_vec(data.font.into_owned()).expect("Invalid bundled font")
+    })
+}
 
-    while (end.x - x).abs() > 0.7 || (end.y - y).abs() > 0.7 {
-        //println!("point: {} {}", x, y);
-        let double_error = error * 2.0;
+/// Placement of a text annotation, in image pixels.
+pub struct TextLayout {
+    /// from the top of a line to its baseline
+    pub ascent: f32,
+    pub row_height: f32,
+    /// the background, padding included
+    pub background: Rect,
+}
 
-        if double_error > -delta_y {
-            error -= delta_y;
-            x += step_x;
-        }
+pub fn text_layout(pos: Pos2, text: &TextBox) -> TextLayout {
+    let font = text_font();
+    let scaled = font.as_scaled(text.size);
+    let row_height = scaled.ascent() - scaled.descent() + scaled.line_gap();
+    let lines: Vec<&str> = text.text.split('\n').collect();
+    let width = lines.iter().map(|line| {
+        line.chars().map(|c| scaled.h_advance(font.glyph_id(c))).sum::<f32>()
+    }).fold(0., f32::max);
+    let background = Rect::from_min_size(pos, vec2(width, row_height * lines.len() as f32))
+        .expand(text.size * TEXT_PADDING);
+    TextLayout { ascent: scaled.ascent(), row_height, background }
+}
 
-        if double_error < delta_x {
-            error += delta_x;
-            y += step_y;
+fn draw_text(img: &mut RgbaImage, pos: Pos2, text: &TextBox, color: Color32) {
+    let font = text_font();
+    let scaled = font.as_scaled(text.size);
+    let layout = text_layout(pos, text);
+    let lines: Vec<&str> = text.text.split('\n').collect();
+
+    if let Some(background) = text.background {
+        let rect = layout.background;
+        for y in rect.min.y.round() as i32..rect.max.y.round() as i32 {
+            for x in rect.min.x.round() as i32..rect.max.x.round() as i32 {
+                blend_pixel(img, x, y, background, 1.);
+            }
         }
-        // let num_pixels = (length / thickness).ceil() as usize;
-        // for i in 0..num_pixels {
-        //     let t = i as f32 / (num_pixels - 1) as f32; // Spread the pixels along the line
-        //     let pixel_pos = start + normalized_direction * (length * t) - offset;
-        //     pixels.push(pixel_pos);
-        // }
-        let num_iterations = (thickness * 2.) + 1.;
-        let step_size = thickness / num_iterations;
-
-        //println!("{}", step_size);
+    }
 
-        let thickness_ = thickness / 2.;
-        for dx in -thickness_ as i32..=thickness_ as i32 {
-            for dy in -thickness_ as i32..=thickness_ as i32 {
-                let new_x = x + dx as f32;
-                let new_y = y + dy as f32;
-                pixels.push(Pos2::new(new_x, new_y));
+    for (i, line) in lines.iter().enumerate() {
+        let mut caret = ab_glyph::point(pos.x, pos.y + layout.ascent + layout.row_height * i as f32);
+        let mut previous = None;
+        for c in line.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret.x += scaled.kern(previous, id);
+            }
+            previous = Some(id);
+            let glyph = id.with_scale_and_position(text.size, caret);
+            caret.x += scaled.h_advance(id);
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|x, y, coverage| {
+                    blend_pixel(img, bounds.min.x as i32 + x as i32, bounds.min.y as i32 + y as i32, color, coverage);
+                });
             }
         }
-
-        pixels.push(Pos2::new(x, y));
     }
-
-    pixels
-}
-/*
-fn compute_x_on_rect(p1: Pos2, p2: Pos2, x_r: f32) -> f32 {
-    ((x_r - p1.x) * ((p2.y - p1.y) / (p2.x - p1.x))) + p1.y
 }
 
-fn compute_y_on_rect(p1: Pos2, p2: Pos2, y_r: f32) -> f32 {
-    ((y_r - p1.y) * ((p2.x - p1.x) / (p2.y - p2.x))) + p1.x
+/** sostituisce i pixel dell'area, l'originale non é recuperabile dal risultato **/
+fn redact(img: &mut RgbaImage, rect: Rect, redaction: &Redaction) {
+    let x0 = rect.min.x.max(0.).round() as u32;
+    let y0 = rect.min.y.max(0.).round() as u32;
+    let x1 = (rect.max.x.round().max(0.) as u32).min(img.width());
+    let y1 = (rect.max.y.round().max(0.) as u32).min(img.height());
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+    let mut area = image::imageops::crop_imm(img, x0, y0, x1 - x0, y1 - y0).to_image();
+    let block = ((x1 - x0).max(y1 - y0) / REDACT_BLOCKS).max(REDACT_MIN_BLOCK);
+    match redaction {
+        Redaction::BlackBox => {
+            area.pixels_mut().for_each(|p| *p = image::Rgba([0, 0, 0, 255]));
+        }
+        Redaction::Pixelate => pixelate(&mut area, block),
+        Redaction::Blur => {
+            //blocks first: a plain blur of the original could be partially reverted
+            pixelate(&mut area, block);
+            area = image::imageops::blur(&area, block as f32 / 2.);
+        }
+    }
+    image::imageops::replace(img, &area, x0 as i64, y0 as i64);
 }
- */
-
 
-fn distance(p1: Vec2) -> f32 {
-    ((p1.x * p1.x) + (p1.y * p1.y)).sqrt() as f32
+fn pixelate(img: &mut RgbaImage, block: u32) {
+    for by in (0..img.height()).step_by(block as usize) {
+        for bx in (0..img.width()).step_by(block as usize) {
+            let w = block.min(img.width() - bx);
+            let h = block.min(img.height() - by);
+            let mut sum = [0u32; 3];
+            for y in by..by + h {
+                for x in bx..bx + w {
+                    let p = img.get_pixel(x, y);
+                    sum[0] += p[0] as u32;
+                    sum[1] += p[1] as u32;
+                    sum[2] += p[2] as u32;
+                }
+            }
+            let n = w * h;
+            let color = image::Rgba([(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8, 255]);
+            for y in by..by + h {
+                for x in bx..bx + w {
+                    img.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
 }
 
-fn circle_equation(center: Pos2/*, point_on_circle: Pos2*/) -> impl Fn(f32, f32) -> f32 {
-    //let radius = distance(point_on_circle - center);
-
-    move |x, rad| (center.y + ((rad * rad - (x - center.x) * (x - center.x)).sqrt()))
-}
-/*
-fn perpendicular_line_equation(start: Pos2, end: Pos2) -> impl Fn(f32) -> f32 {
-    let m = -(end.x - start.x)/(end.y - start.y);
-    let b = start.y - m * start.x;
-    move |x| m * x + b
+/** fonde il colore nel pixel, `coverage` é la parte di pixel coperta (0-1) **/
+fn blend_pixel(img: &mut RgbaImage, x: i32, y: i32, color: Color32, coverage: f32) {
+    if x < 0 || y < 0 || x as u32 >= img.width() || y as u32 >= img.height() {
+        return;
+    }
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let alpha = a as f32 / 255.0 * coverage.clamp(0., 1.);
+    let pixel = img.get_pixel_mut(x as u32, y as u32);
+    *pixel = image::Rgba([
+        (pixel[0] as f32 * (1.0 - alpha) + r as f32 * alpha) as u8,
+        (pixel[1] as f32 * (1.0 - alpha) + g as f32 * alpha) as u8,
+        (pixel[2] as f32 * (1.0 - alpha) + b as f32 * alpha) as u8,
+        255
+    ]);
 }
- */
\ No newline at end of file
@@ -1,33 +1,38 @@
 use std::fs;
 use std::path::PathBuf;
-use eframe::Frame;
 use egui::{CollapsingHeader, Ui, CentralPanel, Context, ScrollArea, Color32};
-use crate::myapp::{Config, Layouts, PADDING, render_header};
-use crate::myapp::imglib::restore_dim;
+use crate::myapp::{Config, PADDING, render_header};
+use crate::myapp::framelib::AppFrame;
+use crate::myapp::statelib::{AppEvent, AppState};
 
 
 pub struct MyPath{
-    pub path: PathBuf
+    pub path: PathBuf,
+    /// the settings file could not be written
+    error: Option<String>,
 }
 
 impl MyPath {
     pub fn new(path: PathBuf) -> Self {
-        Self{ path }
+        Self{ path, error: None }
     }
-    pub fn path_layout(&mut self, ctx: &Context, _frame: &mut Frame, layout: &mut Layouts, config: &mut Config, dim: &Option<(f32, f32)>){
+    pub fn path_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame, state: &mut AppState, config: &mut Config){
         //_frame.set_window_size(egui::vec2(300.0, 360.0));
         //render_top_panel(ctx, _frame);
         CentralPanel::default().show(ctx, |ui| {
             render_header(ui, "PATH");
-            self.render_path_body(_frame, ui, config, layout, dim);
+            self.render_path_body(_frame, ui, config, state);
         });
     }
 
-    pub fn render_path_body(&mut self, _frame: &mut Frame, ui: &mut Ui, config: &mut Config, layout: &mut Layouts, dim: &Option<(f32, f32)>) {
+    pub fn render_path_body(&mut self, _frame: &mut dyn AppFrame, ui: &mut Ui, config: &mut Config, state: &mut AppState) {
         //let paths = self.path.clone();
         //ui.horizontal(|ui| {
         ui.add_space(2. * PADDING);
         ui.label(format!("Destination Path : {}", self.path.clone().into_os_string().into_string().unwrap()));
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::LIGHT_RED, format!("Unable to save the settings: {}", e));
+        }
         ui.add_space(2. * PADDING);
         ui.separator();
         ui.add_space(2. * PADDING);
@@ -38,14 +43,19 @@ impl MyPath {
         ui.horizontal(|ui|{
             if ui.button("Change Path").clicked() {
                 config.path = self.path.clone();
-                confy::store("screenshot", "screenshot", &config).unwrap();
-                restore_dim(dim, _frame, Some(Layouts::Home));
-                *layout = Layouts::Home;
+                //the path stays in use for this session, and the screen open to show why it is not kept
+                match config.store() {
+                    Ok(()) => {
+                        self.error = None;
+                        state.handle(AppEvent::Back, _frame);
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
             }
             if ui.button("↩").clicked() {
                 self.path = config.path.clone();
-                restore_dim(dim, _frame, Some(Layouts::Home));
-                *layout = Layouts::Home;
+                self.error = None;
+                state.handle(AppEvent::Back, _frame);
             }
         });
     }
@@ -0,0 +1,276 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local};
+use egui::{Slider, Ui};
+use image::{ImageError, RgbaImage};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+use serde::{Serialize, Deserialize};
+use crate::myapp::gallerylib::CaptureIndex;
+use crate::myapp::projectlib::open_rendered;
+use crate::myapp::savelib::{MySave, SaveError};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PageSize {
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    /** larghezza e altezza in punti (1/72 di pollice), in verticale **/
+    fn points(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.28, 841.89),
+            PageSize::Letter => (612., 792.),
+        }
+    }
+}
+
+/// Page layout of PDF exports and reports.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PdfOptions {
+    pub page: PageSize,
+    pub landscape: bool,
+    pub per_page: u8,
+    /// file name and timestamp under each capture
+    pub footer: bool,
+    /// caption of a single capture saved as PDF, asked in the save dialog every time
+    #[serde(skip)]
+    pub caption: String,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self { page: PageSize::A4, landscape: false, per_page: 1, footer: true, caption: String::new() }
+    }
+}
+
+impl PdfOptions {
+    pub fn options_ui(&mut self, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                for page in [PageSize::A4, PageSize::Letter] {
+                    ui.selectable_value(&mut self.page, page, format!("{:?}", page));
+                }
+                ui.checkbox(&mut self.landscape, "Landscape");
+            });
+            ui.add(Slider::new(&mut self.per_page, 1..=4).text("Captures per page"));
+            ui.checkbox(&mut self.footer, "File name and time");
+        });
+    }
+}
+
+/// A capture placed in the PDF.
+pub struct PdfEntry {
+    pub image: RgbaImage,
+    pub caption: String,
+    pub name: String,
+    pub timestamp: DateTime<Local>,
+}
+
+impl PdfEntry {
+    /** apre un'immagine o un progetto (con le annotazioni), la data viene dalla cronologia o dal file **/
+    pub fn open(path: &Path, caption: String, index: &CaptureIndex) -> Result<Self, String> {
+        let image = open_rendered(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        //the history keeps canonical paths
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let timestamp = index.captures
+            .iter()
+            .rev()
+            .find(|r| r.path == canonical)
+            .map(|r| r.timestamp)
+            .or_else(|| fs::metadata(path).and_then(|m| m.modified()).ok().map(DateTime::from))
+            .unwrap_or_else(Local::now);
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        Ok(Self { image, caption, name, timestamp })
+    }
+}
+
+/** report con le catture e le loro didascalie, `captions` nello stesso ordine di `paths`, gli orari dall'indice in `index_path` **/
+pub fn open_entries(paths: &[PathBuf], captions: &[String], index_path: &Path) -> Result<Vec<PdfEntry>, String> {
+    //a missing history only loses the original timestamps
+    let index = CaptureIndex::load(index_path).unwrap_or_default();
+    paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| PdfEntry::open(path, captions.get(i).cloned().unwrap_or_default(), &index))
+        .collect()
+}
+
+/** nome predefinito dei report, senza estensione **/
+pub fn report_name() -> String {
+    format!("report_{}", Local::now().format("%Y-%m-%d_%H%M%S"))
+}
+
+/** scrive il report come le altre immagini, ritorna la dimensione del file **/
+pub fn write_report(path: &Path, entries: &[PdfEntry], options: &PdfOptions) -> Result<u64, SaveError> {
+    MySave::write_atomically(path, |file| Ok(encode_pdf(entries, options, file)?))
+}
+
+const MARGIN: f32 = 36.;
+const GAP: f32 = 18.;
+const CAPTION_SIZE: f32 = 11.;
+const FOOTER_SIZE: f32 = 8.;
+const LINE_SPACING: f32 = 1.3;
+
+/** pdf con `options.per_page` catture per pagina, ciascuna con didascalia e piede opzionali **/
+pub fn encode_pdf<W: Write>(entries: &[PdfEntry], options: &PdfOptions, mut writer: W) -> Result<(), ImageError> {
+    let (mut page_w, mut page_h) = options.page.points();
+    if options.landscape {
+        (page_w, page_h) = (page_h, page_w);
+    }
+    let per_page = options.per_page.clamp(1, 4) as usize;
+    let columns = if per_page > 2 || (options.landscape && per_page == 2) { 2 } else { 1 };
+    let rows = per_page.div_ceil(columns);
+    let cell_w = (page_w - 2. * MARGIN - GAP * (columns - 1) as f32) / columns as f32;
+    //the page number is in the bottom margin
+    let cell_h = (page_h - 2. * MARGIN - GAP * (rows - 1) as f32) / rows as f32;
+
+    let mut pdf = Pdf::new();
+    let mut next_id = 1;
+    let mut new_ref = || {
+        next_id += 1;
+        Ref::new(next_id)
+    };
+    let catalog_id = new_ref();
+    let tree_id = new_ref();
+    let font_id = new_ref();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.type1_font(font_id).base_font(Name(b"Helvetica")).encoding_predefined(Name(b"WinAnsiEncoding"));
+
+    let pages: Vec<&[PdfEntry]> = if entries.is_empty() { vec![&[]] } else { entries.chunks(per_page).collect() };
+    let mut page_ids = vec![];
+    for (page_number, page_entries) in pages.iter().enumerate() {
+        let page_id = new_ref();
+        let content_id = new_ref();
+        page_ids.push(page_id);
+        let mut content = Content::new();
+        let mut images = vec![];
+        for (i, entry) in page_entries.iter().enumerate() {
+            let x = MARGIN + (i % columns) as f32 * (cell_w + GAP);
+            let top = page_h - MARGIN - (i / columns) as f32 * (cell_h + GAP);
+            let caption = wrap(&entry.caption, cell_w, CAPTION_SIZE);
+            let mut text_h = caption.len() as f32 * CAPTION_SIZE * LINE_SPACING;
+            if options.footer {
+                text_h += FOOTER_SIZE * LINE_SPACING;
+            }
+
+            //the image fills the cell above the text, centered and never upscaled past 1px = 1pt
+            let (w, h) = (entry.image.width() as f32, entry.image.height() as f32);
+            let scale = (cell_w / w).min((cell_h - text_h - 4.).max(1.) / h).min(1.);
+            let (img_w, img_h) = (w * scale, h * scale);
+            let img_x = x + (cell_w - img_w) / 2.;
+            let img_y = top - img_h;
+            let name = format!("Im{}", i);
+            images.push((name.clone(), write_image(&mut pdf, &mut new_ref, &entry.image)));
+            content.save_state();
+            content.transform([img_w, 0., 0., img_h, img_x, img_y]);
+            content.x_object(Name(name.as_bytes()));
+            content.restore_state();
+
+            let mut y = img_y - 4.;
+            for line in &caption {
+                y -= CAPTION_SIZE * LINE_SPACING;
+                text(&mut content, x, y, CAPTION_SIZE, 0., line);
+            }
+            if options.footer {
+                y -= FOOTER_SIZE * LINE_SPACING;
+                let footer = format!("{} - {}", entry.name, entry.timestamp.format("%Y-%m-%d %H:%M:%S"));
+                text(&mut content, x, y, FOOTER_SIZE, 0.4, &footer);
+            }
+        }
+        let number = format!("{} / {}", page_number + 1, pages.len());
+        text(&mut content, page_w - MARGIN - 30., MARGIN - FOOTER_SIZE, FOOTER_SIZE, 0.4, &number);
+
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0., 0., page_w, page_h));
+        page.parent(tree_id);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        resources.fonts().pair(Name(b"F1"), font_id);
+        resources.x_objects().pairs(images.iter().map(|(name, id)| (Name(name.as_bytes()), *id)));
+        resources.finish();
+        page.finish();
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.pages(tree_id).count(page_ids.len() as i32).kids(page_ids);
+    writer.write_all(&pdf.finish())?;
+    Ok(())
+}
+
+/** immagine rgb compressa, con la trasparenza in una soft mask solo se serve **/
+fn write_image(pdf: &mut Pdf, new_ref: &mut impl FnMut() -> Ref, img: &RgbaImage) -> Ref {
+    let id = new_ref();
+    let rgb: Vec<u8> = img.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect();
+    let mask = if img.pixels().any(|p| p[3] < 255) {
+        let mask_id = new_ref();
+        let alpha: Vec<u8> = img.pixels().map(|p| p[3]).collect();
+        let alpha = compress_to_vec_zlib(&alpha, 6);
+        let mut mask = pdf.image_xobject(mask_id, &alpha);
+        mask.filter(Filter::FlateDecode);
+        mask.width(img.width() as i32);
+        mask.height(img.height() as i32);
+        mask.color_space().device_gray();
+        mask.bits_per_component(8);
+        mask.finish();
+        Some(mask_id)
+    } else {
+        None
+    };
+    let rgb = compress_to_vec_zlib(&rgb, 6);
+    let mut image = pdf.image_xobject(id, &rgb);
+    image.filter(Filter::FlateDecode);
+    image.width(img.width() as i32);
+    image.height(img.height() as i32);
+    image.color_space().device_rgb();
+    image.bits_per_component(8);
+    if let Some(mask_id) = mask {
+        image.s_mask(mask_id);
+    }
+    image.finish();
+    id
+}
+
+fn text(content: &mut Content, x: f32, y: f32, size: f32, gray: f32, line: &str) {
+    content.set_fill_gray(gray);
+    content.begin_text();
+    content.set_font(Name(b"F1"), size);
+    content.next_line(x, y);
+    content.show(Str(&win_ansi(line)));
+    content.end_text();
+}
+
+/** la font standard usa WinAnsi: i caratteri fuori da Latin-1 diventano '?' **/
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x20..=0x7e | 0xa0..=0xff => c as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/** divide il testo in righe larghe al massimo `width`, con la larghezza media dei caratteri di Helvetica **/
+fn wrap(text: &str, width: f32, size: f32) -> Vec<String> {
+    let max_chars = ((width / (size * 0.5)) as usize).max(1);
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use image::{ImageFormat, RgbaImage};
+use serde::{Serialize, Deserialize};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use crate::myapp::capturelib::CaptureInfo;
+use crate::myapp::historylib::{Annotation, Command, History};
+use crate::myapp::paintlib::Painting;
+use crate::myapp::savelib::{MySave, SaveError, SaveResult, Saved};
+
+/// A project is a zip with the image without annotations and the annotation list.
+pub const PROJECT_EXTENSION: &str = "shot";
+const IMAGE_ENTRY: &str = "image.png";
+const ANNOTATIONS_ENTRY: &str = "annotations.json";
+/// 2 adds the undo history
+const PROJECT_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct AnnotationsRef<'a> {
+    version: u32,
+    capture: CaptureInfo,
+    painting: &'a Painting,
+    history: StoredHistory,
+}
+
+#[derive(Deserialize)]
+struct Annotations {
+    version: u32,
+    capture: CaptureInfo,
+    painting: Painting,
+    #[serde(default)]
+    history: StoredHistory,
+}
+
+/// `Command` as written in a project: the images of a crop are PNG entries of the zip, by number.
+#[derive(Serialize, Deserialize)]
+enum StoredCommand {
+    Add(usize, Annotation),
+    Delete(usize, Annotation),
+    Edit(usize, Annotation, Annotation, String),
+    Clear(Vec<Annotation>),
+    Crop {
+        image: usize,
+        cropped: usize,
+        shapes: Vec<Annotation>,
+        cropped_shapes: Vec<Annotation>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StoredHistory {
+    done: Vec<StoredCommand>,
+    undone: Vec<StoredCommand>,
+}
+
+impl StoredHistory {
+    /** i comandi con le immagini dei tagli tolte e messe in `images` **/
+    fn new(history: &History, images: &mut Vec<RgbaImage>) -> Self {
+        let mut store = |command: &Command| match command {
+            Command::Add(i, a) => StoredCommand::Add(*i, a.clone()),
+            Command::Delete(i, a) => StoredCommand::Delete(*i, a.clone()),
+            Command::Edit(i, before, after, what) => StoredCommand::Edit(*i, before.clone(), after.clone(), what.clone()),
+            Command::Clear(old) => StoredCommand::Clear(old.clone()),
+            Command::Crop { image, cropped, shapes, cropped_shapes } => {
+                images.push(image.clone());
+                images.push(cropped.clone());
+                StoredCommand::Crop { image: images.len() - 2, cropped: images.len() - 1, shapes: shapes.clone(), cropped_shapes: cropped_shapes.clone() }
+            }
+        };
+        let (done, undone) = history.commands();
+        Self { done: done.iter().map(&mut store).collect(), undone: undone.iter().map(&mut store).collect() }
+    }
+
+    /** la cronologia con le immagini dei tagli lette dall'archivio **/
+    fn load(self, archive: &mut ZipArchive<fs::File>) -> Result<History, String> {
+        let mut load = |command: StoredCommand| -> Result<Command, String> {
+            Ok(match command {
+                StoredCommand::Add(i, a) => Command::Add(i, a),
+                StoredCommand::Delete(i, a) => Command::Delete(i, a),
+                StoredCommand::Edit(i, before, after, what) => Command::Edit(i, before, after, what),
+                StoredCommand::Clear(old) => Command::Clear(old),
+                StoredCommand::Crop { image, cropped, shapes, cropped_shapes } => Command::Crop {
+                    image: read_png(archive, &history_entry(image))?,
+                    cropped: read_png(archive, &history_entry(cropped))?,
+                    shapes,
+                    cropped_shapes,
+                },
+            })
+        };
+        let done = self.done.into_iter().map(&mut load).collect::<Result<_, _>>()?;
+        let undone = self.undone.into_iter().map(&mut load).collect::<Result<_, _>>()?;
+        Ok(History::from_commands(done, undone))
+    }
+}
+
+fn history_entry(n: usize) -> String {
+    format!("history/{}.png", n)
+}
+
+fn read_entry(archive: &mut ZipArchive<fs::File>, name: &str) -> Result<Vec<u8>, String> {
+    let mut data = vec![];
+    archive.by_name(name)
+        .map_err(|e| format!("{}: {}", name, e))?
+        .read_to_end(&mut data)
+        .map_err(|e| format!("{}: {}", name, e))?;
+    Ok(data)
+}
+
+fn read_png(archive: &mut ZipArchive<fs::File>, name: &str) -> Result<RgbaImage, String> {
+    image::load_from_memory_with_format(&read_entry(archive, name)?, ImageFormat::Png)
+        .map(|i| i.to_rgba8())
+        .map_err(|e| format!("{}: {}", name, e))
+}
+
+/// What a project holds besides the image, taken from the editor before saving on another thread.
+pub struct ProjectData {
+    annotations: Vec<u8>,
+    /// images of the crops in the undo history
+    images: Vec<RgbaImage>,
+}
+
+/// A capture that can be edited again: the annotations are not burned into `image`.
+pub struct Project {
+    pub image: RgbaImage,
+    pub capture: CaptureInfo,
+    pub painting: Painting,
+}
+
+impl Project {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let annotations: Annotations = serde_json::from_slice(&read_entry(&mut archive, ANNOTATIONS_ENTRY)?)
+            .map_err(|e| format!("{}: {}", ANNOTATIONS_ENTRY, e))?;
+        if annotations.version > PROJECT_VERSION {
+            return Err(format!("project version {} is newer than this app", annotations.version));
+        }
+        let image = read_png(&mut archive, IMAGE_ENTRY)?;
+        let mut painting = annotations.painting;
+        painting.set_history(annotations.history.load(&mut archive)?);
+        Ok(Self { image, capture: annotations.capture, painting })
+    }
+
+    /** immagine con le annotazioni disegnate, come la salverebbe l'editor **/
+    pub fn render(&mut self) -> RgbaImage {
+        self.painting
+            .edit_rgba(self.image.clone())
+            .and_then(|(rgba, w, h)| RgbaImage::from_raw(w, h, rgba))
+            .unwrap_or_else(|| self.image.clone())
+    }
+}
+
+pub fn is_project(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case(PROJECT_EXTENSION))
+}
+
+/** apre un'immagine, per i progetti la versione con le annotazioni **/
+pub fn open_rendered(path: &Path) -> Result<RgbaImage, String> {
+    if is_project(path) {
+        Ok(Project::load(path)?.render())
+    } else {
+        image::open(path).map(|i| i.to_rgba8()).map_err(|e| e.to_string())
+    }
+}
+
+/** annotazioni in json e cronologia, da calcolare prima di spostare il salvataggio su un altro thread **/
+pub fn project_data(painting: &Painting, capture: CaptureInfo) -> serde_json::Result<ProjectData> {
+    let mut images = vec![];
+    let history = StoredHistory::new(painting.history(), &mut images);
+    let annotations = serde_json::to_vec_pretty(&AnnotationsRef { version: PROJECT_VERSION, capture, painting, history })?;
+    Ok(ProjectData { annotations, images })
+}
+
+/** scrive il progetto nello stesso modo atomico delle immagini, ritorna la dimensione del file **/
+pub fn write_project(path: &Path, image: &RgbaImage, data: &ProjectData) -> Result<u64, SaveError> {
+    let png = |image: &RgbaImage| -> Result<Vec<u8>, SaveError> {
+        let mut png = Cursor::new(vec![]);
+        image.write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    };
+    let mut entries = vec![(IMAGE_ENTRY.to_string(), png(image)?)];
+    for (n, image) in data.images.iter().enumerate() {
+        entries.push((history_entry(n), png(image)?));
+    }
+    MySave::write_atomically(path, |file| {
+        let mut zip = ZipWriter::new(file);
+        for (name, png) in &entries {
+            //the png is compressed already
+            zip.start_file(name.as_str(), FileOptions::default().compression_method(CompressionMethod::Stored))
+                .map_err(|e| SaveError::Io(e.into()))?;
+            zip.write_all(png)?;
+        }
+        zip.start_file(ANNOTATIONS_ENTRY, FileOptions::default())
+            .map_err(|e| SaveError::Io(e.into()))?;
+        zip.write_all(&data.annotations)?;
+        zip.finish().map_err(|e| SaveError::Io(e.into()))?;
+        Ok(())
+    })
+}
+
+pub fn save_project_tokio(image: RgbaImage, data: ProjectData, mut path: PathBuf, name: String,
+                          tx: Sender<SaveResult>, ctx: egui::Context) {
+    tokio::spawn(async move {
+        let instant = std::time::Instant::now();
+        path.push(format!("{}.{}", name, PROJECT_EXTENSION));
+        let result = MySave::generate_unique_filename(&path).and_then(|path| {
+            let size = write_project(&path, &image, &data)?;
+            Ok(Saved {
+                path,
+                size,
+                width: image.width(),
+                height: image.height(),
+                duration: instant.elapsed(),
+                report: false,
+            })
+        });
+        let _ = tx.send(result);
+        ctx.request_repaint();
+    });
+}
@@ -1,39 +1,109 @@
 use chrono::{Local, DateTime};
-use std::path::PathBuf;
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, ErrorKind};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
 use std::time::Duration;
 use egui::{Color32, Grid};
 use image::RgbaImage;
+use image::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use crate::myapp::exportlib::{encode_jpeg, encode_png, ExportOptions};
+use crate::myapp::giflib::encode_gif;
 use crate::myapp::imglib::AllFormats;
+use crate::myapp::pdflib::{encode_pdf, PdfEntry};
+use crate::myapp::svglib::{encode_svg, VectorImage};
 use crate::myapp::PADDING;
 use crate::myapp::pathlib::MyPath;
+use crate::myapp::framelib::AppFrame;
+use crate::myapp::statelib::{AppEvent, AppState};
+
+#[derive(Debug)]
+pub enum SaveError {
+    NoImage,
+    /// the buffer does not match width and height
+    InvalidBuffer,
+    Io(std::io::Error),
+    Encode(image::ImageError),
+    /// the encoder panicked, nothing was written
+    Crashed,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::NoImage => write!(f, "there is no image to save"),
+            SaveError::InvalidBuffer => write!(f, "the image buffer is corrupted"),
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::Encode(e) => write!(f, "unable to encode the image: {}", e),
+            SaveError::Crashed => write!(f, "the encoder crashed"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for SaveError {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::IoError(e) => SaveError::Io(e),
+            e => SaveError::Encode(e),
+        }
+    }
+}
+
+/// A file written by the save task.
+#[derive(Debug)]
+pub struct Saved {
+    pub path: PathBuf,
+    /// bytes on disk
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    pub duration: Duration,
+    /// a PDF report of the gallery, not a capture: it stays out of the history
+    pub report: bool,
+}
+
+pub type SaveResult = Result<Saved, SaveError>;
 
 pub struct MySave {
     path: MyPath,
     name: String,
     format: AllFormats,
+    options: ExportOptions,
     rgba: Option<(Vec<u8>, u32, u32)>,
+    /// annotations kept as shapes, for SVG
+    vector: Option<VectorImage>,
     name_error: bool,
-    tx: Sender<Duration>,
+    tx: Sender<SaveResult>,
 }
 
 impl MySave {
-    pub fn new(path: PathBuf, format: AllFormats, rgba: &(Vec<u8>, u32, u32), tx: Sender<Duration>) -> Self {
-        let current_datetime: DateTime<Local> = Local::now();
-        let formatted_datetime = current_datetime.format("%Y-%m-%d_%H%M%S");
-        let name = formatted_datetime.to_string();
+    pub fn new(path: PathBuf, format: AllFormats, options: ExportOptions, name: String, rgba: &(Vec<u8>, u32, u32),
+               vector: Option<VectorImage>, tx: Sender<SaveResult>) -> Self {
         let path = MyPath::new(path);
         Self {
             path,
             name,
             format,
+            options,
             rgba: Some(rgba.clone()),
+            vector,
             name_error: false,
             tx,
         }
     }
 
-    pub fn save_body(&mut self, ui: &mut egui::Ui, open_save: &mut bool, saving: &mut bool) {
+    pub fn save_body(&mut self, ui: &mut egui::Ui, state: &mut AppState, frame: &mut dyn AppFrame) {
         let paths = self.path.path.clone();
         if self.name_error {
             ui.colored_label(Color32::RED, "Name syntax error");
@@ -56,11 +126,22 @@ impl MySave {
                     .show_ui(ui, |ui| {
                         ui.style_mut().wrap = Some(false);
                         ui.set_min_width(60.0);
-                        ui.selectable_value(&mut self.format, AllFormats::PNG, "PNG");
-                        ui.selectable_value(&mut self.format, AllFormats::JPEG, "JPEG");
-                        ui.selectable_value(&mut self.format, AllFormats::GIF, "GIF");
+                        for format in AllFormats::all() {
+                            let label = format!("{:?}", format);
+                            ui.selectable_value(&mut self.format, format, label);
+                        }
                     });
                 ui.end_row();
+                if ExportOptions::has_options(&self.format) {
+                    ui.label("Options");
+                    self.options.options_ui(ui, &self.format);
+                    ui.end_row();
+                }
+                if self.format == AllFormats::PDF {
+                    ui.label("Caption");
+                    ui.add(egui::TextEdit::multiline(&mut self.options.pdf.caption).desired_rows(2));
+                    ui.end_row();
+                }
                 ui.label("Destination Path :");
                 ui.label(paths.clone().into_os_string().into_string().unwrap());
             });
@@ -73,14 +154,21 @@ impl MySave {
                 if Self::is_file_name_valid(self.name.as_str()){
                     //funzione per salvare
                     //self.save_image();
-                    MySave::save_image_tokio(self.rgba.clone(),
-                                             self.path.path.clone(),
-                                             self.name.clone(),
-                                             self.format.clone(),
-                                             self.tx.clone(),
-                                             ui.ctx().clone());
-                    *saving = true;
-                    *open_save = false;
+                    match (&self.format, &self.vector) {
+                        (AllFormats::SVG, Some(vector)) => MySave::save_vector_tokio(vector.clone(),
+                                                                                    self.path.path.clone(),
+                                                                                    self.name.clone(),
+                                                                                    self.tx.clone(),
+                                                                                    ui.ctx().clone()),
+                        _ => MySave::save_image_tokio(self.rgba.clone(),
+                                                      self.path.path.clone(),
+                                                      self.name.clone(),
+                                                      self.format.clone(),
+                                                      self.options.clone(),
+                                                      self.tx.clone(),
+                                                      ui.ctx().clone()),
+                    }
+                    state.handle(AppEvent::SaveStarted, frame);
                 }
                 else{
                     //banner di errore nel nome
@@ -88,50 +176,57 @@ impl MySave {
                 }
             }
             if ui.button("Cancel").clicked() {
-                *open_save = false;
+                state.handle(AppEvent::CloseSave, frame);
             }
         });
     }
 
+    pub fn default_name() -> String {
+        let current_datetime: DateTime<Local> = Local::now();
+        current_datetime.format("%Y-%m-%d_%H%M%S").to_string()
+    }
+
     pub fn is_file_name_valid(file_name: &str) -> bool {
-        file_name.chars().all(|c| c.is_ascii_alphanumeric() || c=='-' || c=='_')
+        //subfolders are allowed, the name template can create them
+        file_name.split('/').all(|part| {
+            !part.is_empty() && part != "." && part != ".."
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c=='-' || c=='_' || c=='.')
+        })
         //file_name.chars().all(|c| c.is_ascii_alphanumeric())
     }
 
     pub fn save_image_tokio(rgba: Option<(Vec<u8>, u32, u32)>, path: PathBuf,
-                            name: String, format: AllFormats,
-                            tx: Sender<Duration>, ctx: egui::Context) {// rgba, path, name, format, tx
+                            name: String, format: AllFormats, options: ExportOptions,
+                            tx: Sender<SaveResult>, ctx: egui::Context) {// rgba, path, name, format, tx
         tokio::spawn(async move {
             let instant = std::time::Instant::now();
-            let (rgba, w, h) = rgba.clone().take().unwrap();
-            let mut p = path.clone();
-            let mut name = name.clone();
-            if name.trim() == "" {
-                let current_datetime: DateTime<Local> = Local::now();
-                let formatted_datetime = current_datetime.format("%Y-%m-%d_%H%M%S");
-                name = formatted_datetime.to_string();
-            }
-
-            p.push(format!("{}.{}", name, format.to_string()));
-            let p = Self::generate_unique_filename(&p);
+            let result = rgba.ok_or(SaveError::NoImage).and_then(|(rgba, w, h)| {
+                let mut name = name.clone();
+                if name.trim() == "" {
+                    name = Self::default_name();
+                }
+                let p = Self::unique_path(path.clone(), &name, &format)?;
+                let size = Self::write_image(&rgba, w, h, &p, &format, &options)?;
+                Ok(Saved { path: p, size, width: w, height: h, duration: instant.elapsed(), report: false })
+            });
+            //the window may be closed already, nobody to notify
+            let _ = tx.send(result);
+            ctx.request_repaint();
+        });
+    }
 
-            if format == AllFormats::GIF {
-                let reuced_width = w / 4;
-                let reuced_height = h / 4;
-                let rgba_image: RgbaImage = image::ImageBuffer::from_raw(
-                    w,
-                    h,
-                    rgba.to_owned(),
-                )
-                    .unwrap();
-                let reduced_image = image::imageops::resize(&rgba_image, reuced_width, reuced_height, image::imageops::FilterType::Lanczos3);
-                reduced_image.save_with_format(p, image::ImageFormat::Gif).unwrap();
-            } else {
-                image::save_buffer(p, rgba.as_slice(), w, h, image::ColorType::Rgba8).unwrap();
-            }
-            let duration = instant.elapsed();
-            //println!("Time elapsed in expensive_function() is: {:?}", duration);
-            tx.send(duration).unwrap();
+    /** come `save_image_tokio`, ma in svg con le annotazioni come forme **/
+    pub fn save_vector_tokio(vector: VectorImage, path: PathBuf, name: String,
+                             tx: Sender<SaveResult>, ctx: egui::Context) {
+        tokio::spawn(async move {
+            let instant = std::time::Instant::now();
+            let name = if name.trim() == "" { Self::default_name() } else { name };
+            let (width, height) = vector.background.dimensions();
+            let result = Self::unique_path(path, &name, &AllFormats::SVG).and_then(|p| {
+                let size = Self::write_atomically(&p, |file| Ok(encode_svg(&vector, file)?))?;
+                Ok(Saved { path: p, size, width, height, duration: instant.elapsed(), report: false })
+            });
+            let _ = tx.send(result);
             ctx.request_repaint();
         });
     }
@@ -231,19 +326,115 @@ impl MySave {
         println!("Time elapsed in expensive_function() is: {:?}", duration);
     }
 */
-    fn generate_unique_filename(path: &PathBuf) -> PathBuf {
-        let mut attempt = 0;
-        let mut new_path = path.clone();
+    /** nome del file nella cartella, senza sovrascrivere quelli esistenti: il file viene giá
+    creato vuoto, vedi `generate_unique_filename` **/
+    pub fn unique_path(mut dir: PathBuf, name: &str, format: &AllFormats) -> Result<PathBuf, SaveError> {
+        dir.push(format!("{}.{}", name, format.to_string()));
+        Self::generate_unique_filename(&dir)
+    }
+
+    /** codifica il buffer rgba nel formato scelto e lo scrive su disco, ritorna la dimensione del file **/
+    pub fn write_image(rgba: &[u8], w: u32, h: u32, path: &Path, format: &AllFormats, options: &ExportOptions) -> Result<u64, SaveError> {
+        Self::write_atomically(path, |file| {
+            let rgba_image: RgbaImage = image::ImageBuffer::from_raw(w, h, rgba.to_owned())
+                .ok_or(SaveError::InvalidBuffer)?;
+            Self::encode_to(&rgba_image, file, path, format, options)
+        })
+    }
+
+    fn encode_to(img: &RgbaImage, file: &mut BufWriter<fs::File>, path: &Path, format: &AllFormats, options: &ExportOptions) -> Result<(), SaveError> {
+        match format {
+            AllFormats::JPEG => encode_jpeg(img, &options.jpeg, file)?,
+            AllFormats::PNG => encode_png(img, &options.png, file)?,
+            AllFormats::GIF => encode_gif(img, &options.gif, file)?,
+            //annotations, if any, are already in the pixels
+            AllFormats::SVG => encode_svg(&VectorImage::plain(img.clone()), file)?,
+            AllFormats::PDF => {
+                let entry = PdfEntry {
+                    image: img.clone(),
+                    caption: options.pdf.caption.clone(),
+                    name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                    timestamp: Local::now(),
+                };
+                encode_pdf(&[entry], &options.pdf, file)?
+            }
+            _ => {
+                let image_format = format.image_format().ok_or(image::ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(ImageFormatHint::Name(format.to_string()),
+                                                           UnsupportedErrorKind::Format(ImageFormatHint::Name(format.to_string())))))?;
+                img.write_to(file, image_format)?
+            }
+        }
+        Ok(())
+    }
 
-        while new_path.exists() {
+    /** scrive su un file temporaneo nella stessa cartella, rinominato solo se `write` riesce:
+    `path` o é completo o non esiste. Un file vuoto al suo posto é la prenotazione di `unique_path`
+    e se il salvataggio fallisce viene tolto. Ritorna la dimensione del file **/
+    pub fn write_atomically(path: &Path, write: impl FnOnce(&mut BufWriter<fs::File>) -> Result<(), SaveError>) -> Result<u64, SaveError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let (temp, file) = Self::temp_file(path)?;
+        let written = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), SaveError> {
+            let mut file = BufWriter::new(file);
+            write(&mut file)?;
+            let file = file.into_inner().map_err(|e| SaveError::Io(e.into_error()))?;
+            //data must be on disk before the rename makes the file visible
+            file.sync_all()?;
+            Ok(())
+        }))
+            .unwrap_or(Err(SaveError::Crashed))
+            .and_then(|_| fs::rename(&temp, path).map_err(SaveError::from));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp);
+            if fs::metadata(path).is_ok_and(|m| m.len() == 0) {
+                let _ = fs::remove_file(path);
+            }
+            return Err(e);
+        }
+        Ok(fs::metadata(path)?.len())
+    }
+
+    /** file temporaneo accanto a `path`, diverso per ogni salvataggio anche dello stesso file:
+    pid e un contatore del processo, creato solo se non esiste giá **/
+    fn temp_file(path: &Path) -> Result<(PathBuf, fs::File), SaveError> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        loop {
+            let n = NEXT.fetch_add(1, Ordering::Relaxed);
+            let temp = path.with_file_name(format!(".{}.{}-{}.part", file_name, std::process::id(), n));
+            match fs::OpenOptions::new().write(true).create_new(true).open(&temp) {
+                Ok(file) => return Ok((temp, file)),
+                //left by a crashed run with the same pid
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /** primo nome libero tra `path`, `nome_(1)`, `nome_(2)`...: il file viene creato vuoto nello
+    stesso momento in cui si controlla, cosí due salvataggi partiti insieme non prendono lo stesso nome **/
+    pub fn generate_unique_filename(path: &Path) -> Result<PathBuf, SaveError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut attempt = 0;
+        let mut new_path = path.to_path_buf();
+        loop {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&new_path) {
+                Ok(_) => return Ok(new_path),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
             attempt += 1;
-            let file_stem = path.file_stem().unwrap().to_string_lossy();
-            let extension = path.extension().unwrap().to_string_lossy();
-            let new_filename = format!("{}_({}).{}", file_stem, attempt, extension);
+            let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let new_filename = match path.extension() {
+                Some(extension) => format!("{}_({}).{}", file_stem, attempt, extension.to_string_lossy()),
+                None => format!("{}_({})", file_stem, attempt),
+            };
             new_path.set_file_name(new_filename);
         }
-
-        new_path
     }
 
 }
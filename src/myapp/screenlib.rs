@@ -1,19 +1,14 @@
 use std::borrow::Cow;
-use std::io::{ Cursor };
-use crate::myapp::imglib::{ restore_dim };
 use egui::*;
-use eframe::Frame;
-use screenshots::{Image, Screen};
-use crate::myapp::{Layouts};
+use crate::myapp::framelib::AppFrame;
+use crate::myapp::statelib::{AppEvent, AppState};
+use crate::myapp::capturelib::{capture_displays, capture_rect, composite, default_backend, physical_layout, physical_rect, CaptureBackend, CaptureError, CaptureInfo, CaptureMode, Monitor, ScreenRect};
+use crate::myapp::windowlib::{list_windows, window_at, WindowInfo};
 use arboard::{Clipboard, ImageData};
-use image::{GenericImage, RgbaImage};
-
-struct ScreenImage {
-    screen: Screen,
-    image: Image,
-}
+use image::{RgbaImage};
 
 pub struct MyScreenshot {
+    backend: Box<dyn CaptureBackend>,
     clicked: Option<ButtonClicked>,
     screenshot: bool,
     count: i32,
@@ -23,15 +18,26 @@ pub struct MyScreenshot {
     ending_point: Pos2,
     middle_point: Pos2,
     dimensions_selected: Vec2,
+    /// top-level windows while choosing one to capture, topmost first
+    windows: Option<Vec<WindowInfo>>,
+    /// window capture includes the frame of the window manager
+    decorations: bool,
+    error: Option<String>,
+    /// display to capture (1 = first), `None` captures all of them
+    pub monitor: Option<usize>,
+    /// displays listed when the overlay opens, for the picker
+    displays: Option<Vec<Monitor>>,
 }
 #[derive(PartialEq)]
 enum ButtonClicked {
     FullScreen,
     GrabbedScreen,
+    Window(ScreenRect),
 }
 impl Default for MyScreenshot {
     fn default() -> Self {
         Self{
+            backend: default_backend(),
             clicked: None,
             screenshot: false,
             count: 0,
@@ -41,20 +47,120 @@ impl Default for MyScreenshot {
             ending_point: Default::default(),
             middle_point: Default::default(),
             dimensions_selected: Default::default(),
+            windows: None,
+            decorations: true,
+            error: None,
+            monitor: None,
+            displays: None,
         }
     }
 }
 impl MyScreenshot {
+    pub fn new(backend: Box<dyn CaptureBackend>, monitor: Option<usize>) -> Self {
+        Self { backend, monitor, ..Default::default() }
+    }
+
+    /** lo schermo scelto, se esiste ancora **/
+    fn chosen_display(&self) -> Option<(usize, Monitor)> {
+        let n = self.monitor?;
+        self.displays.as_ref()?.get(n.checked_sub(1)?).map(|m| (n, *m))
+    }
+
+    /** dove sono gli schermi in pixel fisici, solo quello scelto se c'é **/
+    fn physical_area(&self) -> Option<Vec<ScreenRect>> {
+        let layout = physical_layout(self.displays.as_ref()?);
+        match self.chosen_display() {
+            Some((n, _)) => Some(vec![layout[n - 1]]),
+            None => Some(layout),
+        }
+    }
+
+    /**cattura le schermate selezionate e le unisce in un'unica immagine**/
+    fn grab(&self) -> Result<(RgbaImage, CaptureInfo), CaptureError> {
+        if let Some(ButtonClicked::Window(rect)) = self.clicked {
+            let (img, monitor) = capture_rect(self.backend.as_ref(), rect)?;
+            return Ok((img, CaptureInfo { mode: CaptureMode::Window, monitor }));
+        }
+        if let Some(ButtonClicked::GrabbedScreen) = self.clicked {
+            let (img, monitor) = capture_rect(self.backend.as_ref(), self.selection()?)?;
+            return Ok((img, CaptureInfo { mode: CaptureMode::Region, monitor }));
+        }
+        let mut displays = self.backend.displays()?;
+        let monitor = match self.chosen_display() {
+            Some((n, chosen)) => {
+                displays = vec![chosen];
+                Some(n)
+            }
+            None if displays.len() == 1 => Some(1),
+            None => None,
+        };
+        let captures = capture_displays(self.backend.as_ref(), displays)?;
+        Ok((composite(captures)?, CaptureInfo { mode: CaptureMode::Full, monitor }))
+    }
+
+    /** area coperta dalla finestra di cattura (lo schermo scelto o tutto il desktop) in pixel logici,
+    e la sua origine in pixel fisici **/
+    fn overlay_area(&self) -> Option<(Rect, Pos2)> {
+        let displays = match self.chosen_display() {
+            Some((_, monitor)) => vec![monitor],
+            None => self.displays.clone()?,
+        };
+        let logical = displays
+            .iter()
+            .map(|m| Rect::from_min_size(pos2(m.x as f32, m.y as f32), vec2(m.width as f32, m.height as f32)))
+            .reduce(|a, b| a.union(b))?;
+        //each display has its own scale, the physical origin is not logical * scale of the whole area
+        let physical = self.physical_area()?
+            .into_iter()
+            .map(|r| pos2(r.x as f32, r.y as f32))
+            .reduce(|a, b| a.min(b))?;
+        Some((logical, physical))
+    }
+
+    /** la selezione in pixel fisici del desktop, limitata allo schermo scelto: l'overlay é in pixel
+    logici e ogni parte della selezione va scalata come lo schermo su cui sta **/
+    fn selection(&self) -> Result<ScreenRect, CaptureError> {
+        let (logical, _) = self.overlay_area().ok_or(CaptureError::NoDisplays)?;
+        let displays = self.displays.as_ref().ok_or(CaptureError::NoDisplays)?;
+        let min = logical.min + self.starting_point.to_vec2();
+        let rect = physical_rect(displays, min.x, min.y, self.dimensions_selected.x, self.dimensions_selected.y);
+        match self.chosen_display().and(self.physical_area()) {
+            Some(chosen) => rect.intersect(&chosen[0]).ok_or(CaptureError::InvalidArea),
+            None => Ok(rect),
+        }
+    }
+
     /**schermata screen**/
-    pub fn screen_layout(&mut self, ctx: &Context, _frame: &mut Frame,
-                         layout: &mut Layouts, img_: &mut Option<RgbaImage>,
+    pub fn screen_layout(&mut self, ctx: &Context, _frame: &mut dyn AppFrame,
+                         state: &mut AppState, img_: &mut Option<RgbaImage>, capture: &mut CaptureInfo,
                          clipboard: &mut Option<Clipboard>)
     {
-        let width = _frame.info().window_info.monitor_size.unwrap().x;
-        let height = _frame.info().window_info.monitor_size.unwrap().y;
+        if self.displays.is_none() {
+            match self.backend.displays() {
+                Ok(displays) => self.displays = Some(displays),
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    self.displays = Some(vec![]);
+                }
+            }
+        }
+        let mut width = _frame.monitor_size().unwrap().x;
+        let mut height = _frame.monitor_size().unwrap().y;
+        let mut origin = pos2(0., 0.);
+        //the overlay covers the chosen display or every display, so a selection can span them
+        if let Some((area, _)) = self.overlay_area() {
+            width = area.width();
+            height = area.height();
+            origin = area.min;
+        }
+        //the toolbar stays on the primary display when the overlay spans several
+        let mut toolbar = pos2((width - 30.) / 2.0, 0.0);
+        if let (None, Some(primary)) = (self.chosen_display(), self.displays.iter().flatten().find(|m| m.is_primary)) {
+            toolbar = pos2(primary.x as f32 - origin.x + (primary.width as f32 - 30.) / 2.0, primary.y as f32 - origin.y);
+        }
         _frame.set_decorations(false);
         _frame.set_window_size(vec2(width + 1., height + 1.));
-        _frame.set_window_pos(pos2(0., 0.));
+        _frame.set_window_pos(origin);
         ctx.set_cursor_icon(CursorIcon::Default);
         if ctx.is_pointer_over_area() {
             ctx.set_cursor_icon(CursorIcon::Crosshair);
@@ -63,88 +169,30 @@ impl MyScreenshot {
         if self.screenshot {
             ctx.set_cursor_icon(CursorIcon::Wait);
             //println!("screenshot.is_some()");
-            let screen_images = Screen::all().unwrap()
-                .into_iter()
-                .filter(|screen| {
-                    if self.clicked == Some(ButtonClicked::GrabbedScreen){
-                        let rect = Rect::from_two_pos(
-                            Pos2::new(screen.display_info.x as f32, screen.display_info.y as f32),
-                            Pos2::new(
-                                (screen.display_info.x + screen.display_info.width as i32) as f32,
-                                (screen.display_info.y + screen.display_info.height as i32) as f32,
-                            ),
-                        );
-                        rect.contains(self.starting_point)
-                    } else {
-                        true
-                    }
-                })
-                .map(|screen| {
-                    let image;
-                    match self.clicked.as_ref() {
-                        Some(ButtonClicked::FullScreen) => {
-                            image = screen.capture().unwrap();
-                        }
-                        Some(ButtonClicked::GrabbedScreen) => {
-                            image = screen.capture_area((self.starting_point.x * ctx.pixels_per_point()) as i32, (self.starting_point.y * ctx.pixels_per_point()) as i32, (self.dimensions_selected.x * ctx.pixels_per_point()) as u32, (self.dimensions_selected.y * ctx.pixels_per_point()) as u32).unwrap(); //TODO: inserire sreen grab
-                        }
-                        _ => image = screen.capture().unwrap()
+            match self.grab() {
+                Ok((img, info)) => {
+                    let img_data =  ImageData {
+                        width: img.width() as usize,
+                        height: img.height() as usize,
+                        bytes: Cow::from(img.to_vec()),
+                    };
+                    if let Some(clip) = clipboard.as_mut() {
+                        clip.set_image(img_data.to_owned_img()).unwrap();
                     }
-                    ScreenImage{ screen, image }
-                })
-                .collect::<Vec<_>>();
-            // Compute coordinates of combined image
-            let x_min = screen_images.iter().map(|s| s.screen.display_info.x).min().unwrap();
-            let y_min = screen_images.iter().map(|s| s.screen.display_info.y).min().unwrap();
-            let x_max = screen_images
-                .iter()
-                .map(|s| s.screen.display_info.x + s.image.width() as i32)
-                .max()
-                .unwrap();
-            let y_max = screen_images
-                .iter()
-                .map(|s| s.screen.display_info.y + s.image.height() as i32)
-                .max()
-                .unwrap();
-
-            // Compute size and offset of combined image
-            let offset = (x_min, y_min);
-            let size = ((x_max - x_min) as u32, (y_max - y_min) as u32);
-            //println!("Total screenshot size: {:?}", size);
-            //println!("Offset: {:?}", offset);
-
-            // Allocate combined image
-            let mut img = RgbaImage::new(size.0, size.1);
-
-            for screen_image in screen_images {
-                let screenshot = image::io::Reader::new(Cursor::new(screen_image.image.to_png().unwrap()))
-                    .with_guessed_format()
-                    .unwrap()
-                    .decode()
-                    .unwrap();
-                img.copy_from(
-                    &screenshot,
-                    (screen_image.screen.display_info.x - offset.0) as u32,
-                    (screen_image.screen.display_info.y - offset.1) as u32,
-                )
-                    .unwrap();
-            }
-            let img_data =  ImageData {
-                width: img.width() as usize,
-                height: img.height() as usize,
-                bytes: Cow::from(img.to_vec()),
-            };
-            if let Some(clip) = clipboard.as_mut() {
-                clip.set_image(img_data.to_owned_img()).unwrap();
+                    *img_ = Some(img);
+                    *capture = info;
+                }
+                Err(e) => eprintln!("Unable to take the screenshot: {}", e),
             }
-            *img_ = Some(RgbaImage::from(img));
             self.clicked = None;
             self.screenshot = false;
             self.count = 0;
-            _frame.set_decorations(true);
+            self.windows = None;
+            self.displays = None;
+            state.handle(AppEvent::CaptureDone, _frame);
+            //room for the editor
             _frame.set_window_size(vec2(0.3 * width + 200., 500.));
             _frame.set_window_pos(pos2(0., 0.));
-            *layout = Layouts::Home;
         }
         if self.clicked.is_some() {
             self.count += 1;
@@ -156,7 +204,12 @@ impl MyScreenshot {
         Area::new("screen")
             .show(ctx, |ui| {
 
-                let response = ui.allocate_response(ctx.available_rect().size(), Sense::drag());
+                let sense = if self.windows.is_some() { Sense::click() } else { Sense::drag() };
+                let response = ui.allocate_response(ctx.available_rect().size(), sense);
+                if self.windows.is_some() {
+                    self.window_picker(ui, &response);
+                    return;
+                }
                 let bound = response.rect.size();
                 if response.drag_started() {
                     self.starting_point = ctx.pointer_interact_pos().unwrap();
@@ -226,7 +279,7 @@ impl MyScreenshot {
         if self.clicked.is_none() {
             Window::new("TAKE A SCREENSHOT")
                 .title_bar(false)
-                .fixed_pos(pos2((width - 30.) / 2.0, 0.0))
+                .fixed_pos(toolbar)
                 .show(ctx, |ui| {
                     self.id = Some(ui.layer_id());
                     ui.horizontal(|ui| {
@@ -235,11 +288,21 @@ impl MyScreenshot {
                             if ui.button("🖵").clicked() {
                                 self.clicked = Some(ButtonClicked::FullScreen);
                             }
+                            if ui.selectable_label(self.windows.is_some(), "🗔").on_hover_text("Window").clicked() {
+                                self.toggle_windows();
+                            }
+                            if self.windows.is_some() {
+                                ui.checkbox(&mut self.decorations, "Frame");
+                            }
+                            self.display_picker(ui);
+                            if let Some(e) = &self.error {
+                                ui.colored_label(Color32::LIGHT_RED, e);
+                            }
                             ui.separator();
                             if ui.button("◀").clicked() {
-                                _frame.set_decorations(true);
-                                restore_dim(&None, _frame, Some(Layouts::Home));
-                                *layout = Layouts::Home;
+                                self.windows = None;
+                                self.displays = None;
+                                state.handle(AppEvent::CaptureDone, _frame);
                             }
                             if self.clicked.is_some() {
                                 ui.set_visible(false);
@@ -250,4 +313,63 @@ impl MyScreenshot {
                 });
         }
     }
+
+    /** scelta dello schermo da catturare, con risoluzione e scala di ognuno **/
+    fn display_picker(&mut self, ui: &mut Ui) {
+        let displays = self.displays.clone().unwrap_or_default();
+        if displays.len() < 2 {
+            return;
+        }
+        let selected = match self.chosen_display() {
+            Some((n, monitor)) => monitor.label(n),
+            None => "All displays".to_string(),
+        };
+        ComboBox::from_id_source("display_picker")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.style_mut().wrap = Some(false);
+                ui.selectable_value(&mut self.monitor, None, "All displays");
+                for (i, monitor) in displays.iter().enumerate() {
+                    ui.selectable_value(&mut self.monitor, Some(i + 1), monitor.label(i + 1));
+                }
+            });
+    }
+
+    /** entra o esce dalla modalitá finestra, la lista delle finestre viene letta una volta sola **/
+    fn toggle_windows(&mut self) {
+        self.error = None;
+        if self.windows.take().is_some() {
+            return;
+        }
+        match list_windows() {
+            Ok(windows) => self.windows = Some(windows),
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    /**evidenzia la finestra sotto al puntatore e la cattura al click**/
+    fn window_picker(&mut self, ui: &mut Ui, response: &Response) {
+        //the frames before the capture must not show the outline, it would end up in the image
+        if self.clicked.is_some() {
+            ui.ctx().request_repaint();
+            return;
+        }
+        let ppp = ui.ctx().pixels_per_point();
+        let Some(pointer) = ui.ctx().pointer_hover_pos() else { return };
+        let windows = self.windows.as_deref().unwrap_or_default();
+        //window positions are on the whole desktop, the overlay may start somewhere else
+        let (dx, dy) = self.overlay_area().map(|(_, origin)| (origin.x, origin.y)).unwrap_or_default();
+        let Some(window) = window_at(windows, (pointer.x * ppp + dx) as i32, (pointer.y * ppp + dy) as i32) else { return };
+        let rect = window.rect(self.decorations);
+        let area = Rect::from_min_size(pos2((rect.x as f32 - dx) / ppp, (rect.y as f32 - dy) / ppp),
+                                       vec2(rect.width as f32, rect.height as f32) / ppp);
+        ui.painter().rect(area, Rounding::none(), Color32::from_rgba_unmultiplied(255, 255, 255, 2),
+                          Stroke::new(2.0, Color32::WHITE));
+        ui.painter().text(area.left_top() + vec2(4., 4.), Align2::LEFT_TOP, &window.title,
+                          FontId::proportional(14.), Color32::WHITE);
+        if response.clicked() {
+            self.clicked = Some(ButtonClicked::Window(rect));
+            ui.ctx().request_repaint();
+        }
+    }
 }
@@ -0,0 +1,229 @@
+use egui::{vec2, Vec2};
+use crate::myapp::Layouts;
+use crate::myapp::cutlib::MyCut;
+use crate::myapp::framelib::AppFrame;
+
+/// What is open over the home screen, one thing at a time.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Overlay {
+    None,
+    Settings,
+    /// the cut rectangle over the image, annotations wait until it is closed
+    Cut,
+    /// the "save to file" window
+    Save,
+}
+
+/// Where the application is. Home shows the editor, the other screens take the whole window.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Mode {
+    Home(Overlay),
+    /// the transparent window used to capture, `back` is where the capture hotkey returns
+    Capture { back: Layouts },
+    Hotkey,
+    Path,
+    About,
+    History,
+}
+
+impl Mode {
+    pub fn layout(&self) -> Layouts {
+        match self {
+            Mode::Home(_) => Layouts::Home,
+            Mode::Capture { .. } => Layouts::Screenshot,
+            Mode::Hotkey => Layouts::Hotkey,
+            Mode::Path => Layouts::Path,
+            Mode::About => Layouts::About,
+            Mode::History => Layouts::History,
+        }
+    }
+
+    fn from_layout(layout: Layouts) -> Self {
+        match layout {
+            Layouts::Home => Mode::Home(Overlay::None),
+            Layouts::Screenshot => Mode::Capture { back: Layouts::Home },
+            Layouts::Hotkey => Mode::Hotkey,
+            Layouts::Path => Mode::Path,
+            Layouts::About => Mode::About,
+            Layouts::History => Mode::History,
+        }
+    }
+}
+
+/// What the user or a background task asked, the same whether it comes from a button or a hotkey.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AppEvent {
+    ToggleSettings,
+    CloseSettings,
+    /// one of the screens reached from home: Hotkey, Path, About or History
+    Open(Layouts),
+    /// ↩ of those screens
+    Back,
+    /// "+ New", the delay starts from `time`
+    NewCapture { time: f64 },
+    /// the take screenshot hotkey: opens the capture, pressed again goes back
+    CaptureHotkey { time: f64 },
+    /// the capture window closed, with or without an image
+    CaptureDone,
+    /// a new image is in the editor
+    ImageLoaded,
+    OpenCut,
+    CloseCut,
+    OpenSave,
+    CloseSave,
+    /// the save hotkey, with the default name and format
+    SaveHotkey,
+    /// a save started from the save window or as a project
+    SaveStarted,
+    SaveFinished,
+    Copy,
+}
+
+/// The navigation of the application. Events not allowed in the current mode are refused,
+/// so for example the save hotkey does nothing while the cut is open.
+pub struct AppState {
+    mode: Mode,
+    /// size of the window in home, given back when the other screens close
+    dim: Option<Vec2>,
+    /// saves running in tokio, the save window can start one while another is running
+    saving: usize,
+    /// the editor has an image
+    image: bool,
+    /// `ctx` time when the capture was asked
+    capture_time: f64,
+    cut: MyCut,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Home(Overlay::None),
+            dim: None,
+            saving: 0,
+            image: false,
+            capture_time: f64::NEG_INFINITY,
+            cut: MyCut::default(),
+        }
+    }
+}
+
+impl AppState {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn layout(&self) -> Layouts {
+        self.mode.layout()
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.saving > 0
+    }
+
+    pub fn capture_time(&self) -> f64 {
+        self.capture_time
+    }
+
+    /** il rettangolo del taglio, solo mentre é aperto **/
+    pub fn cut(&self) -> Option<&MyCut> {
+        (self.mode == Mode::Home(Overlay::Cut)).then_some(&self.cut)
+    }
+
+    pub fn cut_mut(&mut self) -> Option<&mut MyCut> {
+        (self.mode == Mode::Home(Overlay::Cut)).then_some(&mut self.cut)
+    }
+
+    /** se l'evento verrebbe accettato, per disabilitare i pulsanti **/
+    pub fn allows(&self, event: AppEvent) -> bool {
+        self.next(event).is_some()
+    }
+
+    /** il modo dopo l'evento, None se non é permesso **/
+    fn next(&self, event: AppEvent) -> Option<Mode> {
+        let idle = matches!(self.mode, Mode::Home(Overlay::None | Overlay::Settings));
+        let editing = idle && self.image;
+        match (self.mode, event) {
+            (Mode::Home(Overlay::None), AppEvent::ToggleSettings) => Some(Mode::Home(Overlay::Settings)),
+            (Mode::Home(Overlay::Settings), AppEvent::ToggleSettings | AppEvent::CloseSettings) => Some(Mode::Home(Overlay::None)),
+            (_, AppEvent::Open(layout @ (Layouts::Hotkey | Layouts::Path | Layouts::About | Layouts::History))) if idle => {
+                Some(Mode::from_layout(layout))
+            }
+            (Mode::Hotkey | Mode::Path | Mode::About | Mode::History, AppEvent::Back) => Some(Mode::Home(Overlay::None)),
+            (Mode::Home(_), AppEvent::NewCapture { .. }) => Some(Mode::Capture { back: Layouts::Home }),
+            //the hotkey screen is where the hotkey gets changed
+            (Mode::Hotkey, AppEvent::CaptureHotkey { .. }) => None,
+            (Mode::Capture { back }, AppEvent::CaptureHotkey { .. }) => Some(Mode::from_layout(back)),
+            (mode, AppEvent::CaptureHotkey { .. }) => Some(Mode::Capture { back: mode.layout() }),
+            (Mode::Capture { .. }, AppEvent::CaptureDone) => Some(Mode::Home(Overlay::None)),
+            (mode, AppEvent::ImageLoaded | AppEvent::SaveFinished) => Some(mode),
+            (_, AppEvent::OpenCut) if editing => Some(Mode::Home(Overlay::Cut)),
+            (Mode::Home(Overlay::Cut), AppEvent::CloseCut) => Some(Mode::Home(Overlay::None)),
+            (_, AppEvent::OpenSave) if editing => Some(Mode::Home(Overlay::Save)),
+            (Mode::Home(Overlay::Save), AppEvent::CloseSave | AppEvent::SaveStarted) => Some(Mode::Home(Overlay::None)),
+            (mode, AppEvent::SaveStarted) if editing => Some(mode),
+            (mode, AppEvent::SaveHotkey) if editing && self.saving == 0 => Some(mode),
+            (mode, AppEvent::Copy) if editing => Some(mode),
+            _ => None,
+        }
+    }
+
+    /** applica l'evento con le azioni di uscita e di entrata sulla finestra, false se non é permesso **/
+    pub fn handle(&mut self, event: AppEvent, frame: &mut dyn AppFrame) -> bool {
+        let Some(next) = self.next(event) else { return false };
+        match event {
+            AppEvent::NewCapture { time } | AppEvent::CaptureHotkey { time } => self.capture_time = time,
+            AppEvent::ImageLoaded => self.image = true,
+            AppEvent::SaveStarted | AppEvent::SaveHotkey => self.saving += 1,
+            AppEvent::SaveFinished => self.saving = self.saving.saturating_sub(1),
+            _ => {}
+        }
+        let previous = self.mode;
+        self.mode = next;
+        if next == Mode::Home(Overlay::Cut) && previous != next {
+            self.cut = MyCut::default();
+        }
+        if next.layout() != previous.layout() {
+            self.exit(previous, frame);
+            self.enter(next, frame);
+        }
+        if let AppEvent::CaptureHotkey { .. } = event {
+            //the hotkey also works with the window minimized
+            frame.set_minimized(false);
+            frame.focus();
+        }
+        true
+    }
+
+    /** azioni sulla finestra quando si lascia una schermata **/
+    fn exit(&mut self, mode: Mode, frame: &mut dyn AppFrame) {
+        match mode {
+            Mode::Home(_) => self.dim = Some(frame.window_size()),
+            Mode::Capture { .. } => {
+                frame.set_visible(true);
+                frame.set_decorations(true);
+            }
+            _ => {}
+        }
+    }
+
+    /** azioni sulla finestra quando si entra in una schermata **/
+    fn enter(&mut self, mode: Mode, frame: &mut dyn AppFrame) {
+        match window_size(mode.layout(), self.dim) {
+            Some(size) => frame.set_window_size(size),
+            //the capture window sizes itself on the displays once the delay is over
+            None => frame.set_visible(false),
+        }
+    }
+}
+
+/** dimensione della finestra per ogni schermata, home torna a quella che aveva **/
+pub fn window_size(layout: Layouts, dim: Option<Vec2>) -> Option<Vec2> {
+    match layout {
+        Layouts::Home => Some(dim.unwrap_or(vec2(400., 200.))),
+        Layouts::Path => Some(vec2(400., 480.)),
+        Layouts::Hotkey => Some(vec2(400., 500.)),
+        Layouts::About => Some(vec2(400., 270.)),
+        Layouts::History => Some(vec2(760., 600.)),
+        Layouts::Screenshot => None,
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{Cursor, Write};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use egui::{Color32, Pos2, Rect};
+use image::{ImageError, ImageFormat, RgbaImage};
+use crate::myapp::historylib::Annotation;
+use crate::myapp::paintlib::{arrow_segments, text_layout, Shapes};
+
+/// A bitmap with the redactions burned in and the other annotations, in image pixels, to draw over it.
+#[derive(Clone)]
+pub struct VectorImage {
+    pub background: RgbaImage,
+    pub annotations: Vec<Annotation>,
+}
+
+impl VectorImage {
+    /** immagine senza annotazioni, o con le annotazioni giá disegnate **/
+    pub fn plain(background: RgbaImage) -> Self {
+        Self { background, annotations: vec![] }
+    }
+}
+
+/** svg con la bitmap incorporata come png e ogni annotazione come elemento nativo, modificabile da Inkscape **/
+pub fn encode_svg<W: Write>(img: &VectorImage, mut writer: W) -> Result<(), ImageError> {
+    let mut png = Cursor::new(vec![]);
+    img.background.write_to(&mut png, ImageFormat::Png)?;
+    let (w, h) = img.background.dimensions();
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#)?;
+    writeln!(writer, r#"  <image id="screenshot" width="{w}" height="{h}" xlink:href="data:image/png;base64,{}"/>"#, STANDARD.encode(png.get_ref()))?;
+    //caps and joins of `stroke_path`, so the file looks like the bitmap export
+    writeln!(writer, r#"  <g id="annotations" fill="none" stroke-linecap="butt" stroke-linejoin="miter" stroke-miterlimit="4">"#)?;
+    for annotation in &img.annotations {
+        if let Some(element) = element(annotation) {
+            writeln!(writer, "    {}", element)?;
+        }
+    }
+    writeln!(writer, "  </g>")?;
+    writeln!(writer, "</svg>")?;
+    Ok(())
+}
+
+fn element((shape, points, stroke): &Annotation) -> Option<String> {
+    let stroke_attrs = format!(r#"{} stroke-width="{}""#, paint("stroke", stroke.color), num(stroke.width));
+    match shape {
+        Shapes::None => {
+            Some(format!(r#"<path d="{}" {}/>"#, path_data(std::slice::from_ref(points)), stroke_attrs))
+        }
+        Shapes::Rect => {
+            let rect = Rect::from_two_pos(points[0], points[1]);
+            Some(format!(r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+                         num(rect.min.x), num(rect.min.y), num(rect.width()), num(rect.height()), stroke_attrs))
+        }
+        Shapes::Circle => {
+            Some(format!(r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+                         num(points[0].x), num(points[0].y), num(points[0].distance(points[1])), stroke_attrs))
+        }
+        Shapes::Arrow(head) => {
+            let segments: Vec<Vec<Pos2>> = arrow_segments(points[0], points[1], head).into_iter().map(Vec::from).collect();
+            Some(format!(r#"<path d="{}" {}/>"#, path_data(&segments), stroke_attrs))
+        }
+        Shapes::Text(text) => {
+            let layout = text_layout(points[0], text);
+            let mut element = String::from("<g>");
+            if let Some(background) = text.background {
+                let rect = layout.background;
+                element += &format!(r#"<rect x="{}" y="{}" width="{}" height="{}" {} stroke="none"/>"#,
+                                    num(rect.min.x), num(rect.min.y), num(rect.width()), num(rect.height()), paint("fill", background));
+            }
+            //same bundled font as the bitmap, when installed
+            element += &format!(r#"<text font-family="Ubuntu, sans-serif" font-weight="300" font-size="{}" {} stroke="none" xml:space="preserve">"#,
+                                num(text.size), paint("fill", stroke.color));
+            for (i, line) in text.text.split('\n').enumerate() {
+                element += &format!(r#"<tspan x="{}" y="{}">{}</tspan>"#,
+                                    num(points[0].x), num(points[0].y + layout.ascent + layout.row_height * i as f32), escape(line));
+            }
+            element += "</text></g>";
+            Some(element)
+        }
+        //redactions are already in the bitmap
+        Shapes::Redact(_) | Shapes::Select => None,
+    }
+}
+
+fn path_data(lines: &[Vec<Pos2>]) -> String {
+    lines
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let points: Vec<String> = line.iter().map(|p| format!("{},{}", num(p.x), num(p.y))).collect();
+            format!("M{}", points.join(" L"))
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/** colore e, se non é opaco, la sua opacitá **/
+fn paint(attr: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut paint = format!(r##"{}="#{:02x}{:02x}{:02x}""##, attr, r, g, b);
+    if a < 255 {
+        paint += &format!(r#" {}-opacity="{}""#, attr, num(a as f32 / 255.));
+    }
+    paint
+}
+
+/** due decimali al massimo, senza zeri inutili **/
+fn num(value: f32) -> String {
+    ((value * 100.).round() / 100.).to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
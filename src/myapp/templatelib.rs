@@ -0,0 +1,79 @@
+use chrono::{DateTime, Local};
+use chrono::format::{Item, StrftimeItems};
+use crate::myapp::capturelib::CaptureInfo;
+
+pub const DEFAULT_TEMPLATE: &str = "{date:%Y-%m-%d}_{time:%H%M%S}";
+
+/// Values available to the tokens of a file name template.
+pub struct TemplateValues {
+    pub now: DateTime<Local>,
+    pub capture: CaptureInfo,
+    pub width: u32,
+    pub height: u32,
+    pub counter: u32,
+}
+
+/** nome del file (senza estensione) generato dal modello, puó contenere sottocartelle separate da `/`.
+Token: {date}, {date:%Y}, {time}, {time:%H-%M}, {monitor}, {width}, {height}, {counter}, {counter:04},
+{hostname}, {mode} **/
+pub fn render_template(template: &str, values: &TemplateValues) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or(format!("unclosed '{{' in \"{}\"", &rest[start..]))? + start;
+        let (token, arg) = match rest[start + 1..end].split_once(':') {
+            Some((token, arg)) => (token, Some(arg)),
+            None => (&rest[start + 1..end], None),
+        };
+        name.push_str(&render_token(token, arg, values)?);
+        rest = &rest[end + 1..];
+    }
+    name.push_str(rest);
+
+    let parts: Vec<String> = name
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(sanitize)
+        .collect();
+    if parts.is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    Ok(parts.join("/"))
+}
+
+fn render_token(token: &str, arg: Option<&str>, values: &TemplateValues) -> Result<String, String> {
+    match (token, arg) {
+        ("date", format) => format_time(&values.now, format.unwrap_or("%Y-%m-%d")),
+        ("time", format) => format_time(&values.now, format.unwrap_or("%H%M%S")),
+        ("monitor", None) => Ok(values.capture.monitor.map(|n| n.to_string()).unwrap_or("all".to_string())),
+        ("width", None) => Ok(values.width.to_string()),
+        ("height", None) => Ok(values.height.to_string()),
+        ("counter", None) => Ok(values.counter.to_string()),
+        ("counter", Some(digits)) => {
+            let digits = digits.parse::<usize>().map_err(|_| format!("invalid counter width '{}'", digits))?;
+            Ok(format!("{:0digits$}", values.counter, digits = digits))
+        }
+        ("hostname", None) => Ok(gethostname::gethostname().to_string_lossy().to_string()),
+        ("mode", None) => Ok(values.capture.mode.to_name().to_string()),
+        _ => Err(format!("unknown token {{{}}}", arg.map(|a| format!("{}:{}", token, a)).unwrap_or(token.to_string()))),
+    }
+}
+
+fn format_time(now: &DateTime<Local>, format: &str) -> Result<String, String> {
+    //chrono panics while printing an invalid format, so it is checked first
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(format!("invalid date format '{}'", format));
+    }
+    Ok(now.format_with_items(items.into_iter()).to_string())
+}
+
+/** lascia solo i caratteri accettati da `MySave::is_file_name_valid` **/
+fn sanitize(part: &str) -> String {
+    let part: String = part
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    if part == "." || part == ".." { part.replace('.', "_") } else { part }
+}
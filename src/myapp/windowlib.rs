@@ -0,0 +1,158 @@
+use crate::myapp::capturelib::{CaptureError, ScreenRect};
+
+/// A top-level window that can be captured, with and without the frame drawn by the window manager.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub frame: ScreenRect,
+    pub client: ScreenRect,
+}
+
+impl WindowInfo {
+    pub fn rect(&self, decorations: bool) -> ScreenRect {
+        if decorations { self.frame } else { self.client }
+    }
+}
+
+/// A top-level window as the window system describes it, before choosing the ones to offer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackedWindow {
+    pub info: WindowInfo,
+    /// `_NET_WM_PID` of the client, if it sets one
+    pub pid: Option<u32>,
+}
+
+/** le finestre da proporre dall'alto verso il basso: `stack` é nell'ordine della root, dal basso,
+con None per quelle non visibili o che non hanno risposto. Sono escluse le finestre di `own_pid`
+e quelle di un pixel **/
+pub fn capturable(stack: &[Option<StackedWindow>], own_pid: u32) -> Vec<WindowInfo> {
+    stack.iter()
+        .rev()
+        .flatten()
+        .filter(|w| w.pid != Some(own_pid) && w.info.frame.width > 1 && w.info.frame.height > 1)
+        .map(|w| w.info.clone())
+        .collect()
+}
+
+/** la finestra piú in alto sotto al punto, `windows` va dall'alto verso il basso come la ritorna `list_windows` **/
+pub fn window_at(windows: &[WindowInfo], x: i32, y: i32) -> Option<&WindowInfo> {
+    windows.iter().find(|w| w.frame.contains(x, y))
+}
+
+/** finestre visibili dall'alto verso il basso, escluse quelle di questo processo **/
+#[cfg(target_os = "linux")]
+pub fn list_windows() -> Result<Vec<WindowInfo>, CaptureError> {
+    x11::list_windows().map_err(|e| CaptureError::Backend(e.to_string()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn list_windows() -> Result<Vec<WindowInfo>, CaptureError> {
+    Err(CaptureError::Backend("window capture is only available on X11".to_string()))
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::error::Error;
+    use x11rb::connection::Connection;
+    use x11rb::errors::ReplyError;
+    use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, MapState, Window};
+    use x11rb::rust_connection::RustConnection;
+    use crate::myapp::capturelib::ScreenRect;
+    use super::{capturable, StackedWindow, WindowInfo};
+
+    struct Atoms {
+        wm_state: Atom,
+        net_wm_name: Atom,
+        net_wm_pid: Atom,
+        utf8_string: Atom,
+    }
+
+    pub fn list_windows() -> Result<Vec<WindowInfo>, Box<dyn Error>> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+        let atom = |name: &[u8]| -> Result<Atom, Box<dyn Error>> { Ok(conn.intern_atom(false, name)?.reply()?.atom) };
+        let atoms = Atoms {
+            wm_state: atom(b"WM_STATE")?,
+            net_wm_name: atom(b"_NET_WM_NAME")?,
+            net_wm_pid: atom(b"_NET_WM_PID")?,
+            utf8_string: atom(b"UTF8_STRING")?,
+        };
+        let mut stack = vec![];
+        //children of the root are in stacking order, bottom first
+        for frame in conn.query_tree(root)?.reply()?.children {
+            match window_info(&conn, root, frame, &atoms) {
+                Ok(window) => stack.push(window),
+                //the window was closed while listing, or refuses a request: it is left out
+                Err(ReplyError::X11Error(_)) => stack.push(None),
+                Err(ReplyError::ConnectionError(e)) => return Err(e.into()),
+            }
+        }
+        Ok(capturable(&stack, std::process::id()))
+    }
+
+    /** la finestra con il pid del client, None se non é visibile **/
+    fn window_info(conn: &RustConnection, root: Window, frame: Window, atoms: &Atoms) -> Result<Option<StackedWindow>, ReplyError> {
+        let attributes = conn.get_window_attributes(frame)?.reply()?;
+        if attributes.map_state != MapState::VIEWABLE || attributes.override_redirect {
+            return Ok(None);
+        }
+        //without a window manager there is no frame and the client is the top-level window
+        let client = find_client(conn, frame, atoms)?.unwrap_or(frame);
+        let geometry = conn.get_geometry(client)?.reply()?;
+        let origin = conn.translate_coordinates(client, root, 0, 0)?.reply()?;
+        let info = WindowInfo {
+            id: client,
+            title: title(conn, client, atoms)?,
+            frame: outer_rect(conn, frame)?,
+            client: ScreenRect {
+                x: origin.dst_x as i32,
+                y: origin.dst_y as i32,
+                width: geometry.width as u32,
+                height: geometry.height as u32,
+            },
+        };
+        Ok(Some(StackedWindow { info, pid: pid(conn, client, atoms)? }))
+    }
+
+    /** la posizione di una finestra figlia della root include il bordo, la dimensione no **/
+    fn outer_rect(conn: &RustConnection, window: Window) -> Result<ScreenRect, ReplyError> {
+        let geometry = conn.get_geometry(window)?.reply()?;
+        let border = 2 * geometry.border_width as u32;
+        Ok(ScreenRect {
+            x: geometry.x as i32,
+            y: geometry.y as i32,
+            width: geometry.width as u32 + border,
+            height: geometry.height as u32 + border,
+        })
+    }
+
+    /** la finestra dell'applicazione é quella con WM_STATE, dentro alla cornice del window manager **/
+    fn find_client(conn: &RustConnection, window: Window, atoms: &Atoms) -> Result<Option<Window>, ReplyError> {
+        let state = conn.get_property(false, window, atoms.wm_state, AtomEnum::ANY, 0, 0)?.reply()?;
+        if state.type_ != u32::from(AtomEnum::NONE) {
+            return Ok(Some(window));
+        }
+        for child in conn.query_tree(window)?.reply()?.children {
+            if let Some(client) = find_client(conn, child, atoms)? {
+                return Ok(Some(client));
+            }
+        }
+        Ok(None)
+    }
+
+    fn pid(conn: &RustConnection, window: Window, atoms: &Atoms) -> Result<Option<u32>, ReplyError> {
+        let reply = conn.get_property(false, window, atoms.net_wm_pid, AtomEnum::CARDINAL, 0, 1)?.reply()?;
+        Ok(reply.value32().and_then(|mut values| values.next()))
+    }
+
+    fn title(conn: &RustConnection, window: Window, atoms: &Atoms) -> Result<String, ReplyError> {
+        let name = conn.get_property(false, window, atoms.net_wm_name, atoms.utf8_string, 0, u32::MAX)?.reply()?;
+        if !name.value.is_empty() {
+            return Ok(String::from_utf8_lossy(&name.value).to_string());
+        }
+        //old clients only set the latin-1 WM_NAME
+        let name = conn.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, u32::MAX)?.reply()?;
+        Ok(name.value.iter().map(|&b| b as char).collect())
+    }
+}
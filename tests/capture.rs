@@ -0,0 +1,172 @@
+//! Capture and composition of several displays on `FakeBackend`, without a screen.
+
+use std::path::PathBuf;
+use image::{Rgba, RgbaImage};
+use progetto::myapp::capturelib::{capture_displays, capture_rect, composite, physical_layout, physical_rect, CaptureBackend, CaptureError, FakeBackend, Monitor, ScreenRect};
+
+const LEFT: Rgba<u8> = Rgba([200, 40, 40, 255]);
+
+fn monitor(id: u32, x: i32, y: i32, width: u32, height: u32, scale_factor: f32) -> Monitor {
+    Monitor { id, x, y, width, height, scale_factor, is_primary: id == 0 }
+}
+
+/** schermo con le coordinate fisiche nei canali, per controllare da dove viene ogni pixel **/
+fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 255, 255]))
+}
+
+/// A 1x display and, on its right, a 2x display of the same logical size (the image is twice as large).
+fn mixed_dpi() -> FakeBackend {
+    FakeBackend::new(vec![
+        (monitor(0, 0, 0, 40, 30, 1.), RgbaImage::from_pixel(40, 30, LEFT)),
+        (monitor(1, 40, 0, 40, 30, 2.), gradient(80, 60)),
+    ])
+}
+
+/// Two 1x displays, the second one lower and on the right.
+fn side_by_side() -> FakeBackend {
+    FakeBackend::new(vec![
+        (monitor(0, 0, 0, 40, 30, 1.), RgbaImage::from_pixel(40, 30, LEFT)),
+        (monitor(1, 40, 10, 50, 30, 1.), gradient(50, 30)),
+    ])
+}
+
+#[test]
+fn composite_places_displays_at_their_position() {
+    let backend = side_by_side();
+    let img = composite(capture_displays(&backend, backend.displays().unwrap()).unwrap()).unwrap();
+    assert_eq!(img.dimensions(), (90, 40));
+    assert_eq!(*img.get_pixel(0, 0), LEFT);
+    assert_eq!(*img.get_pixel(39, 29), LEFT);
+    assert_eq!(*img.get_pixel(40, 10), Rgba([0, 0, 255, 255]));
+    assert_eq!(*img.get_pixel(89, 39), Rgba([49, 29, 255, 255]));
+    //the corners no display covers stay transparent
+    assert_eq!(img.get_pixel(60, 5).0[3], 0);
+    assert_eq!(img.get_pixel(10, 35).0[3], 0);
+}
+
+#[test]
+fn composite_of_one_display_is_its_capture() {
+    let capture = gradient(50, 30);
+    let img = composite(vec![(monitor(1, 40, 10, 50, 30, 1.), capture.clone())]).unwrap();
+    assert_eq!(img, capture);
+    assert!(matches!(composite(vec![]), Err(CaptureError::NoDisplays)));
+}
+
+#[test]
+fn capture_rect_inside_one_display() {
+    let backend = side_by_side();
+    let (img, monitor) = capture_rect(&backend, ScreenRect { x: 45, y: 20, width: 10, height: 5 }).unwrap();
+    assert_eq!(monitor, Some(2));
+    assert_eq!(img.dimensions(), (10, 5));
+    //relative to the display, which starts at 40,10
+    assert_eq!(*img.get_pixel(0, 0), Rgba([5, 10, 255, 255]));
+}
+
+#[test]
+fn capture_rect_across_displays() {
+    let backend = side_by_side();
+    let (img, monitor) = capture_rect(&backend, ScreenRect { x: 30, y: 0, width: 20, height: 30 }).unwrap();
+    assert_eq!(monitor, None);
+    assert_eq!(img.dimensions(), (20, 30));
+    assert_eq!(*img.get_pixel(0, 0), LEFT);
+    assert_eq!(*img.get_pixel(10, 10), Rgba([0, 0, 255, 255]));
+    //above the second display
+    assert_eq!(img.get_pixel(15, 5).0[3], 0);
+}
+
+#[test]
+fn capture_rect_outside_the_desktop() {
+    let backend = side_by_side();
+    let result = capture_rect(&backend, ScreenRect { x: 100, y: 0, width: 10, height: 10 });
+    assert!(matches!(result, Err(CaptureError::InvalidArea)));
+}
+
+#[test]
+fn displays_from_a_folder() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("capture").join("displays_from_a_folder");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    gradient(30, 20).save(dir.join("b.png")).unwrap();
+    RgbaImage::from_pixel(10, 20, LEFT).save(dir.join("a.png")).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a display").unwrap();
+
+    let backend = FakeBackend::from_dir(&dir).unwrap();
+    let displays = backend.displays().unwrap();
+    //sorted by name, left to right
+    assert_eq!(displays, vec![monitor(0, 0, 0, 10, 20, 1.), monitor(1, 10, 0, 30, 20, 1.)]);
+    assert_eq!(*backend.capture_area(&displays[1], 5, 5, 100, 100).unwrap().get_pixel(0, 0), Rgba([5, 5, 255, 255]));
+}
+
+#[test]
+fn mixed_dpi_displays_stay_side_by_side() {
+    let backend = mixed_dpi();
+    let layout = physical_layout(&backend.displays().unwrap());
+    //the 2x display starts where the 1x one ends, not at 40 * 2
+    assert_eq!(layout, vec![
+        ScreenRect { x: 0, y: 0, width: 40, height: 30 },
+        ScreenRect { x: 40, y: 0, width: 80, height: 60 },
+    ]);
+
+    let img = composite(capture_displays(&backend, backend.displays().unwrap()).unwrap()).unwrap();
+    assert_eq!(img.dimensions(), (120, 60));
+    assert_eq!(*img.get_pixel(39, 29), LEFT);
+    assert_eq!(*img.get_pixel(40, 0), Rgba([0, 0, 255, 255]));
+    assert_eq!(*img.get_pixel(119, 59), Rgba([79, 59, 255, 255]));
+    //below the 1x display there is nothing
+    assert_eq!(img.get_pixel(20, 45).0[3], 0);
+}
+
+#[test]
+fn mixed_dpi_region_across_the_border() {
+    let backend = mixed_dpi();
+    let (img, monitor) = capture_rect(&backend, ScreenRect { x: 30, y: 10, width: 30, height: 10 }).unwrap();
+    assert_eq!(monitor, None);
+    assert_eq!(img.dimensions(), (30, 10));
+    assert_eq!(*img.get_pixel(9, 0), LEFT);
+    //physical pixel (0, 10) of the 2x display
+    assert_eq!(*img.get_pixel(10, 0), Rgba([0, 10, 255, 255]));
+    assert_eq!(*img.get_pixel(29, 9), Rgba([19, 19, 255, 255]));
+}
+
+/// A selection drawn on the overlay, in logical pixels, scaled like the display it is on.
+#[test]
+fn mixed_dpi_selection() {
+    let backend = mixed_dpi();
+    let displays = backend.displays().unwrap();
+    //all on the 2x display: 10 logical pixels past its left edge
+    let rect = physical_rect(&displays, 50., 5., 20., 10.);
+    assert_eq!(rect, ScreenRect { x: 60, y: 10, width: 40, height: 20 });
+    let (img, monitor) = capture_rect(&backend, rect).unwrap();
+    assert_eq!(monitor, Some(2));
+    assert_eq!(img.dimensions(), (40, 20));
+    assert_eq!(*img.get_pixel(0, 0), Rgba([20, 10, 255, 255]));
+
+    //all on the 1x display
+    assert_eq!(physical_rect(&displays, 5., 5., 20., 10.), ScreenRect { x: 5, y: 5, width: 20, height: 10 });
+
+    //across the border each corner follows its display
+    let rect = physical_rect(&displays, 30., 4., 20., 10.);
+    assert_eq!(rect, ScreenRect { x: 30, y: 4, width: 30, height: 24 });
+    let (img, monitor) = capture_rect(&backend, rect).unwrap();
+    assert_eq!(monitor, None);
+    assert_eq!(*img.get_pixel(9, 0), LEFT);
+    assert_eq!(*img.get_pixel(10, 4), Rgba([0, 8, 255, 255]));
+}
+
+#[test]
+fn stacked_mixed_dpi_displays() {
+    //a 2x display above a wider 1x one
+    let layout = physical_layout(&[monitor(0, 0, 0, 20, 10, 2.), monitor(1, 0, 10, 40, 20, 1.)]);
+    assert_eq!(layout, vec![
+        ScreenRect { x: 0, y: 0, width: 40, height: 20 },
+        ScreenRect { x: 0, y: 20, width: 40, height: 20 },
+    ]);
+}
+
+#[test]
+fn same_scale_is_logical_times_scale() {
+    //as X11 reports it: every display shares the scale
+    let layout = physical_layout(&[monitor(0, 0, 0, 960, 540, 2.), monitor(1, 960, 0, 640, 400, 2.)]);
+    assert_eq!(layout[1], ScreenRect { x: 1920, y: 0, width: 1280, height: 800 });
+}
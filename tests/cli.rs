@@ -0,0 +1,112 @@
+//! The `progetto` binary run as a command line tool, on fake displays read from a folder.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use image::{Rgba, RgbaImage};
+use progetto::myapp::Config;
+
+/** cartella nuova per il test con un display finto di 64x48 e le impostazioni **/
+fn setup(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("displays")).unwrap();
+    RgbaImage::from_pixel(64, 48, Rgba([30, 60, 90, 255])).save(dir.join("displays").join("0.png")).unwrap();
+    dir
+}
+
+/** `progetto capture` con gli argomenti dati, le impostazioni e i salvataggi nella cartella del test **/
+fn capture(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_progetto"))
+        .env("PROGETTO_CAPTURE_DIR", dir.join("displays"))
+        .arg("--config").arg(dir.join("config.toml"))
+        .arg("capture")
+        .args(args)
+        .arg("--out").arg(dir.join("shots"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn region_is_cropped() {
+    let dir = setup("region_is_cropped");
+    for format in ["png", "jpeg", "gif", "webp", "bmp", "tiff", "qoi"] {
+        let output = capture(&dir, &["--region", "10,5,20,8", "--format", format]);
+        assert!(output.status.success(), "{}: {}", format, String::from_utf8_lossy(&output.stderr));
+        let path = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+        assert!(path.starts_with(dir.join("shots")), "{}: {}", format, path.display());
+        assert_eq!(path.extension().unwrap(), format);
+        let img = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (20, 8), "{}", format);
+        if format != "jpeg" {
+            assert_eq!(*img.get_pixel(0, 0), Rgba([30, 60, 90, 255]), "{}", format);
+        }
+    }
+    //the history is next to the chosen settings file
+    assert!(dir.join("captures.json").exists());
+}
+
+#[test]
+fn region_past_u32_max_is_refused() {
+    let dir = setup("region_past_u32_max_is_refused");
+    for region in ["4294967295,0,10,10", "0,4294967295,10,10", "60,0,10,10"] {
+        let output = capture(&dir, &["--region", region]);
+        assert_eq!(output.status.code(), Some(1), "{}", region);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("outside the 64x48 capture"), "{}: {}", region, stderr);
+    }
+    assert!(!dir.join("shots").exists());
+    //a refused capture does not use up a {counter} value
+    assert_eq!(Config::load(dir.join("config.toml")).counter, 0);
+}
+
+#[test]
+fn counter_advances_after_each_saved_capture() {
+    let dir = setup("counter_advances_after_each_saved_capture");
+    std::fs::write(dir.join("config.toml"), "take_screenshot = [0, \"\", \"\"]\nsave_screenshot = [0, \"\", \"\"]\nformat = \"png\"\npath = \".\"\nname_template = \"shot_{counter}\"\n").unwrap();
+    for expected in ["shot_0.png", "shot_1.png"] {
+        let output = capture(&dir, &[]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(dir.join("shots").join(expected).exists(), "{}", String::from_utf8_lossy(&output.stdout));
+    }
+}
+
+#[test]
+fn pdf_report_has_a_page_per_pair_and_stays_out_of_the_history() {
+    let dir = setup("pdf_report_has_a_page_per_pair_and_stays_out_of_the_history");
+    let mut inputs = vec![];
+    for region in ["0,0,20,10", "0,0,30,12"] {
+        let output = capture(&dir, &["--region", region, "--format", "png"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        inputs.push(PathBuf::from(String::from_utf8(output.stdout).unwrap().trim()));
+    }
+    //a file saved by something else, not in the history
+    let other = dir.join("other.png");
+    RgbaImage::from_pixel(40, 16, Rgba([200, 10, 10, 255])).save(&other).unwrap();
+    inputs.push(other);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_progetto"))
+        .arg("--config").arg(dir.join("config.toml"))
+        .arg("pdf")
+        .args(&inputs)
+        .args(["--caption", "first", "--caption", "second", "--per-page", "2"])
+        .arg("--out").arg(dir.join("reports"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let path = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+    assert_eq!(path.extension().unwrap(), "pdf");
+
+    let pdf = lopdf::Document::load(&path).unwrap();
+    assert_eq!(pdf.get_pages().len(), 2);
+    let mut images: Vec<(i64, i64)> = pdf.objects.values()
+        .filter_map(|object| object.as_stream().ok())
+        .filter(|stream| stream.dict.get(b"Subtype").and_then(|s| s.as_name()).ok() == Some(b"Image".as_slice()))
+        .map(|stream| (stream.dict.get(b"Width").unwrap().as_i64().unwrap(), stream.dict.get(b"Height").unwrap().as_i64().unwrap()))
+        .collect();
+    images.sort();
+    assert_eq!(images, vec![(20, 10), (30, 12), (40, 16)]);
+
+    let history = std::fs::read_to_string(dir.join("captures.json")).unwrap();
+    assert_eq!(history.matches("\"path\"").count(), 2, "{}", history);
+    assert!(!history.contains(".pdf"), "{}", history);
+}
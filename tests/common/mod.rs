@@ -0,0 +1,71 @@
+//! Helpers shared by the integration tests.
+//! Every test crate compiles all of them and uses only some.
+#![allow(dead_code)]
+
+use egui::{vec2, Color32, Pos2, Stroke, Vec2};
+use progetto::myapp::framelib::AppFrame;
+use progetto::myapp::historylib::Annotation;
+use progetto::myapp::paintlib::Painting;
+
+/// Size of the image the drawing tests annotate.
+pub const WIDTH: u32 = 320;
+pub const HEIGHT: u32 = 200;
+
+pub fn stroke(width: f32) -> Stroke {
+    Stroke::new(width, Color32::from_rgb(220, 30, 30))
+}
+
+/** annotazioni in pixel di un'immagine `WIDTH`x`HEIGHT` mostrata a `scale` punti per pixel **/
+pub fn painting(annotations: Vec<Annotation>, scale: f32) -> Painting {
+    Painting::with_annotations(vec2(WIDTH as f32, HEIGHT as f32), scale, annotations)
+}
+
+/// Remembers what the application asked to the window.
+pub struct TestFrame {
+    pub size: Vec2,
+    pub pos: Pos2,
+    pub visible: bool,
+    pub decorations: bool,
+    pub minimized: bool,
+    /// size of the display the window is on
+    pub monitor: Vec2,
+}
+
+impl TestFrame {
+    /** finestra visibile con le decorazioni, come all'avvio **/
+    pub fn new(size: Vec2, monitor: Vec2) -> Self {
+        Self { size, pos: Pos2::ZERO, visible: true, decorations: true, minimized: false, monitor }
+    }
+}
+
+impl AppFrame for TestFrame {
+    fn window_size(&self) -> Vec2 {
+        self.size
+    }
+
+    fn monitor_size(&self) -> Option<Vec2> {
+        Some(self.monitor)
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    fn set_minimized(&mut self, minimized: bool) {
+        self.minimized = minimized;
+    }
+
+    fn set_decorations(&mut self, decorations: bool) {
+        self.decorations = decorations;
+    }
+
+    fn set_window_size(&mut self, size: Vec2) {
+        self.size = size;
+    }
+
+    fn set_window_pos(&mut self, pos: Pos2) {
+        self.pos = pos;
+    }
+
+    fn focus(&mut self) {}
+}
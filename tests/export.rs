@@ -0,0 +1,112 @@
+//! Every raster format written with its encoder settings and read back with `image::open`.
+
+use std::path::{Path, PathBuf};
+use image::{Rgba, RgbaImage};
+use progetto::myapp::exportlib::{ExportOptions, JpegOptions, PngCompression, PngFilter, PngOptions, Subsampling};
+use progetto::myapp::giflib::GifOptions;
+use progetto::myapp::imglib::AllFormats;
+use progetto::myapp::savelib::MySave;
+
+fn dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("export").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A gradient for the lossy encoders with a transparent corner.
+fn image() -> RgbaImage {
+    RgbaImage::from_fn(96, 64, |x, y| {
+        let alpha = if x < 16 && y < 16 { 0 } else { 255 };
+        Rgba([(x * 2) as u8, (y * 4) as u8, (x + y) as u8, alpha])
+    })
+}
+
+/** scrive `img` come farebbe il salvataggio e lo rilegge, ritorna l'immagine letta e la dimensione del file **/
+fn round_trip(path: &Path, format: &AllFormats, options: &ExportOptions) -> (RgbaImage, u64) {
+    let img = image();
+    let size = MySave::write_image(&img, img.width(), img.height(), path, format, options).unwrap();
+    assert_eq!(size, std::fs::metadata(path).unwrap().len());
+    (image::open(path).unwrap().to_rgba8(), size)
+}
+
+/** differenza media dei canali rgb rispetto all'originale, sui pixel opachi **/
+fn error(img: &RgbaImage) -> f64 {
+    let original = image();
+    let (sum, n) = original.pixels().zip(img.pixels())
+        .filter(|(o, _)| o[3] == 255)
+        .fold((0u64, 0u64), |(sum, n), (o, p)| {
+            (sum + (0..3).map(|c| o[c].abs_diff(p[c]) as u64).sum::<u64>(), n + 3)
+        });
+    sum as f64 / n as f64
+}
+
+#[test]
+fn lossless_formats_keep_every_pixel() {
+    let dir = dir("lossless");
+    for format in [AllFormats::PNG, AllFormats::WEBP, AllFormats::BMP, AllFormats::TIFF, AllFormats::QOI] {
+        let path = dir.join(format!("image.{}", format.to_string()));
+        let (img, _) = round_trip(&path, &format, &ExportOptions::default());
+        assert!(img == image(), "{} changed the pixels", format.to_string());
+    }
+}
+
+#[test]
+fn png_settings_change_only_the_size() {
+    let dir = dir("png");
+    let mut sizes = vec![];
+    for compression in [PngCompression::Fast, PngCompression::Default, PngCompression::Best] {
+        for filter in [PngFilter::NoFilter, PngFilter::Sub, PngFilter::Up, PngFilter::Avg, PngFilter::Paeth, PngFilter::Adaptive] {
+            let options = ExportOptions { png: PngOptions { compression, filter }, ..ExportOptions::default() };
+            let path = dir.join(format!("{:?}_{:?}.png", compression, filter));
+            let (img, size) = round_trip(&path, &AllFormats::PNG, &options);
+            assert!(img == image(), "{:?} {:?} changed the pixels", compression, filter);
+            sizes.push((compression, size));
+        }
+    }
+    let smallest = |c: PngCompression| sizes.iter().filter(|(s, _)| *s == c).map(|(_, size)| *size).min().unwrap();
+    assert!(smallest(PngCompression::Best) <= smallest(PngCompression::Fast));
+}
+
+#[test]
+fn jpeg_quality_subsampling_and_background() {
+    let dir = dir("jpeg");
+    let mut results = vec![];
+    for quality in [20, 95] {
+        for subsampling in [Subsampling::S444, Subsampling::S422, Subsampling::S420] {
+            let jpeg = JpegOptions { quality, subsampling, background: [0, 200, 0] };
+            let options = ExportOptions { jpeg, ..ExportOptions::default() };
+            let path = dir.join(format!("{}_{}.jpeg", quality, subsampling.to_name().replace(':', "")));
+            let (img, size) = round_trip(&path, &AllFormats::JPEG, &options);
+            assert_eq!(img.dimensions(), (96, 64));
+            //no alpha in JPEG: the transparent corner is the background
+            let corner = img.get_pixel(4, 4);
+            assert!(corner[3] == 255 && corner[0] < 40 && corner[1] > 160 && corner[2] < 40, "{:?}", corner);
+            results.push((quality, subsampling, size, error(&img)));
+        }
+    }
+    for subsampling in [Subsampling::S444, Subsampling::S422, Subsampling::S420] {
+        let find = |q| results.iter().find(|r| r.0 == q && r.1 == subsampling).unwrap();
+        let (low, high) = (find(20), find(95));
+        assert!(low.2 < high.2, "{}: quality 20 is not smaller", subsampling.to_name());
+        assert!(low.3 > high.3, "{}: quality 20 is not worse", subsampling.to_name());
+        assert!(high.3 < 4., "{}: quality 95 is off by {:.1}", subsampling.to_name(), high.3);
+    }
+}
+
+#[test]
+fn gif_settings() {
+    let dir = dir("gif");
+    let half = ExportOptions { gif: GifOptions { scale: 0.5, colors: 32, ..GifOptions::default() }, ..ExportOptions::default() };
+    let (img, _) = round_trip(&dir.join("half.gif"), &AllFormats::GIF, &half);
+    assert_eq!(img.dimensions(), (48, 32));
+
+    let mut errors = vec![];
+    for colors in [8, 256] {
+        let options = ExportOptions { gif: GifOptions { colors, ..GifOptions::default() }, ..ExportOptions::default() };
+        let (img, _) = round_trip(&dir.join(format!("{}.gif", colors)), &AllFormats::GIF, &options);
+        assert_eq!(img.dimensions(), (96, 64));
+        assert_eq!(img.get_pixel(4, 4)[3], 0, "{} colors: the corner is not transparent", colors);
+        errors.push(error(&img));
+    }
+    assert!(errors[1] < errors[0] / 2., "256 colors are off by {:.1}, 8 by {:.1}", errors[1], errors[0]);
+}
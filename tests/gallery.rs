@@ -0,0 +1,39 @@
+//! The history keeps the captures saved while it is open: its edits go to the index on disk.
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+use progetto::myapp::capturelib::CaptureInfo;
+use progetto::myapp::gallerylib::{CaptureIndex, CaptureRecord, MyGallery};
+use progetto::myapp::exportlib::ExportOptions;
+
+fn empty_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("progetto-gallery-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn record(dir: &std::path::Path, name: &str) -> CaptureRecord {
+    CaptureRecord::new(dir.join(name), 64, 48, CaptureInfo::default())
+}
+
+#[test]
+fn tag_edit_keeps_captures_saved_after_opening() {
+    let dir = empty_dir("tags");
+    let index_path = dir.join("captures.json");
+    let first = record(&dir, "first.png");
+    CaptureIndex::append(&index_path, first.clone()).unwrap();
+
+    let (tx, _rx) = mpsc::channel();
+    let mut gallery = MyGallery::new(index_path.clone(), dir.clone(), ExportOptions::default().pdf, tx);
+    //saved by the editor or the cli while the history is open
+    let second = record(&dir, "second.png");
+    CaptureIndex::append(&index_path, second.clone()).unwrap();
+    gallery.set_tags(&first.path, vec!["bug".to_string()]);
+
+    let index = CaptureIndex::load(&index_path).unwrap();
+    let saved: Vec<(PathBuf, Vec<String>)> = index.captures.into_iter().map(|r| (r.path, r.tags)).collect();
+    assert_eq!(saved, vec![(first.path, vec!["bug".to_string()]), (second.path, vec![])]);
+    assert_eq!(gallery.captures().len(), 2, "the history shows the new capture too");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,73 @@
+//! GIF export read back with the `gif` decoder: one frame, the scaled size and no more colors than asked.
+
+use std::collections::HashSet;
+use image::{Rgba, RgbaImage};
+use progetto::myapp::giflib::{encode_gif, GifOptions, Quantizer};
+
+/// Smooth colors, far more than a GIF palette holds, with a transparent band on the left when asked.
+fn photo(transparent: bool) -> RgbaImage {
+    RgbaImage::from_fn(120, 80, |x, y| {
+        let alpha = if transparent && x < 10 { 0 } else { 255 };
+        Rgba([(x * 2) as u8, (y * 3) as u8, ((x + y) % 256) as u8, alpha])
+    })
+}
+
+struct Decoded {
+    frames: usize,
+    size: (u16, u16),
+    /// palette indices used by the first frame, with the transparent one
+    indices: HashSet<u8>,
+    transparent: Option<u8>,
+}
+
+fn decode(data: &[u8]) -> Decoded {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(data).unwrap();
+    let size = (decoder.width(), decoder.height());
+    let first = decoder.read_next_frame().unwrap().expect("no frame").clone();
+    let mut frames = 1;
+    while decoder.read_next_frame().unwrap().is_some() {
+        frames += 1;
+    }
+    Decoded { frames, size, indices: first.buffer.iter().copied().collect(), transparent: first.transparent }
+}
+
+#[test]
+fn gif_has_one_scaled_frame_within_the_palette() {
+    for quantizer in [Quantizer::NeuQuant, Quantizer::MedianCut] {
+        for dither in [false, true] {
+            let options = GifOptions { scale: 0.5, colors: 16, quantizer, dither };
+            let mut data = vec![];
+            encode_gif(&photo(false), &options, &mut data).unwrap();
+            let gif = decode(&data);
+            let case = format!("{} dither {}", quantizer.to_name(), dither);
+            assert_eq!(gif.frames, 1, "{}", case);
+            assert_eq!(gif.size, (60, 40), "{}", case);
+            assert!(gif.indices.len() <= 16, "{}: {} colors", case, gif.indices.len());
+            assert!(gif.indices.len() > 4, "{}: only {} colors", case, gif.indices.len());
+            assert_eq!(gif.transparent, None, "{}", case);
+
+            //the same file through `image`, as the other formats are read
+            let img = image::load_from_memory(&data).unwrap().to_rgba8();
+            let colors: HashSet<[u8; 4]> = img.pixels().map(|p| p.0).collect();
+            assert!(colors.len() <= 16, "{}", case);
+        }
+    }
+}
+
+#[test]
+fn transparent_pixels_use_one_of_the_colors() {
+    let options = GifOptions { colors: 8, ..GifOptions::default() };
+    let mut data = vec![];
+    encode_gif(&photo(true), &options, &mut data).unwrap();
+    let gif = decode(&data);
+    assert_eq!(gif.size, (120, 80));
+    let transparent = gif.transparent.expect("no transparent color");
+    assert!(gif.indices.contains(&transparent));
+    assert!(gif.indices.len() <= 8, "{} colors", gif.indices.len());
+
+    let img = image::load_from_memory(&data).unwrap().to_rgba8();
+    assert_eq!(img.get_pixel(0, 0)[3], 0);
+    assert_eq!(img.get_pixel(119, 79)[3], 255);
+}
@@ -0,0 +1,182 @@
+//! `Painting::edit_rgba` against the reference images in `tests/golden`.
+//! After an intended change of the rendering regenerate them with
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and look at the new files before committing them.
+
+mod common;
+
+use std::path::PathBuf;
+use common::{painting, stroke, HEIGHT, WIDTH};
+use egui::{pos2, vec2, Color32, Stroke};
+use image::{Rgba, RgbaImage};
+use progetto::myapp::historylib::Annotation;
+use progetto::myapp::paintlib::{ArrowHead, Painting, Redaction, Shapes, TextBox};
+
+/// Largest difference of a channel that still counts as the same pixel (anti-aliasing, SIMD paths).
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Share of pixels allowed to differ by more than `CHANNEL_TOLERANCE`.
+const MAX_DIFFERENT: f64 = 0.002;
+
+/// Light squares with a gradient, so redactions and anti-aliasing have something to change.
+fn canvas() -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        if (x / 20 + y / 20) % 2 == 0 {
+            Rgba([230, 230, 230, 255])
+        } else {
+            Rgba([(x * 255 / WIDTH) as u8, (y * 255 / HEIGHT) as u8, 160, 255])
+        }
+    })
+}
+
+/** disegna le annotazioni, in pixel dell'immagine, come se l'anteprima fosse a `scale` punti per pixel **/
+fn render(annotations: Vec<Annotation>, scale: f32) -> RgbaImage {
+    let (rgba, width, height) = painting(annotations, scale).edit_rgba(canvas()).expect("edit_rgba returned nothing");
+    RgbaImage::from_raw(width, height, rgba).expect("wrong buffer size")
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+/** confronta con `tests/golden/<name>.png`, se fallisce scrive l'immagine ottenuta e quella delle differenze **/
+fn assert_golden(name: &str, actual: &RgbaImage) {
+    let path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+    let expected = match image::open(&path) {
+        Ok(img) => img.to_rgba8(),
+        Err(e) => panic!("{}: {}, create it with UPDATE_GOLDEN=1", path.display(), e),
+    };
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{}: size differs from the reference", name);
+
+    let mut different = 0;
+    //differing pixels in red over a faded copy of the reference
+    let diff = RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let (e, a) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+        if e.0.iter().zip(a.0.iter()).any(|(e, a)| e.abs_diff(*a) > CHANNEL_TOLERANCE) {
+            different += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = e.0;
+            Rgba([r / 4 + 191, g / 4 + 191, b / 4 + 191, 255])
+        }
+    });
+    let share = different as f64 / (WIDTH * HEIGHT) as f64;
+    if share > MAX_DIFFERENT {
+        let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&out).unwrap();
+        actual.save(out.join(format!("{}.actual.png", name))).unwrap();
+        diff.save(out.join(format!("{}.diff.png", name))).unwrap();
+        panic!("{}: {} pixels ({:.2}%) differ from {}, see {}", name, different, share * 100., path.display(), out.display());
+    }
+}
+
+#[test]
+fn freehand() {
+    let line = (0..60).map(|i| pos2(20. + i as f32 * 4.5, 100. + (i as f32 / 6.).sin() * 50.)).collect();
+    assert_golden("freehand", &render(vec![(Shapes::None, line, stroke(3.))], 1.));
+}
+
+#[test]
+fn rect_and_circle() {
+    let annotations = vec![
+        (Shapes::Rect, vec![pos2(30., 30.), pos2(150., 120.)], stroke(2.)),
+        (Shapes::Rect, vec![pos2(290., 170.), pos2(180., 100.)], stroke(6.)),
+        (Shapes::Circle, vec![pos2(220., 60.), pos2(260., 60.)], stroke(4.)),
+        //a click without dragging still gives a small circle
+        (Shapes::Circle, vec![pos2(80., 160.), pos2(80., 160.)], stroke(2.)),
+    ];
+    assert_golden("rect_and_circle", &render(annotations, 1.));
+}
+
+#[test]
+fn arrows() {
+    let annotations = vec![
+        (Shapes::Arrow(ArrowHead { double: false, size: 15. }), vec![pos2(20., 180.), pos2(150., 40.)], stroke(3.)),
+        (Shapes::Arrow(ArrowHead { double: true, size: 25. }), vec![pos2(180., 100.), pos2(300., 100.)], stroke(5.)),
+    ];
+    assert_golden("arrows", &render(annotations, 1.));
+}
+
+#[test]
+fn text() {
+    let plain = TextBox { text: "Golden".to_string(), size: 32., background: None };
+    let boxed = TextBox { text: "two\nlines".to_string(), size: 20., background: Some(Color32::BLACK) };
+    let annotations = vec![
+        (Shapes::Text(plain), vec![pos2(20., 20.)], stroke(1.)),
+        (Shapes::Text(boxed), vec![pos2(180., 110.)], Stroke::new(1., Color32::WHITE)),
+    ];
+    assert_golden("text", &render(annotations, 1.));
+}
+
+/** correlazione di Pearson tra la luminanza delle due immagini nel rettangolo **/
+fn correlation(a: &RgbaImage, b: &RgbaImage, (x0, y0, x1, y1): (u32, u32, u32, u32)) -> f64 {
+    let luma = |p: &Rgba<u8>| p[0] as f64 * 0.299 + p[1] as f64 * 0.587 + p[2] as f64 * 0.114;
+    let pairs: Vec<(f64, f64)> = (y0..y1)
+        .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+        .map(|(x, y)| (luma(a.get_pixel(x, y)), luma(b.get_pixel(x, y))))
+        .collect();
+    let n = pairs.len() as f64;
+    let (ma, mb) = pairs.iter().fold((0., 0.), |(sa, sb), (a, b)| (sa + a / n, sb + b / n));
+    let (cov, va, vb) = pairs.iter().fold((0., 0., 0.), |(c, va, vb), (a, b)| {
+        (c + (a - ma) * (b - mb), va + (a - ma).powi(2), vb + (b - mb).powi(2))
+    });
+    cov / (va * vb).sqrt()
+}
+
+#[test]
+fn redactions() {
+    let areas: Vec<(Redaction, (u32, u32, u32, u32))> = [Redaction::Pixelate, Redaction::Blur, Redaction::BlackBox]
+        .into_iter()
+        .enumerate()
+        .map(|(i, redaction)| {
+            let x = 10 + i as u32 * 105;
+            (redaction, (x, 40, x + 95, 160))
+        })
+        .collect();
+    let annotations = areas.iter()
+        .map(|(redaction, (x0, y0, x1, y1))| {
+            (Shapes::Redact(*redaction), vec![pos2(*x0 as f32, *y0 as f32), pos2(*x1 as f32, *y1 as f32)], stroke(1.))
+        })
+        .collect();
+    let redacted = render(annotations, 1.);
+    assert_golden("redactions", &redacted);
+    //the squares of the canvas must not show through
+    for (redaction, area) in &areas[..2] {
+        let r = correlation(&canvas(), &redacted, *area);
+        assert!(r.abs() < 0.3, "{}: correlation {:.2} with the original", redaction.to_name(), r);
+    }
+}
+
+/// Shapes drawn over a half-size preview: strokes and arrow heads get twice as thick in the image.
+#[test]
+fn half_size_preview() {
+    let annotations = vec![
+        (Shapes::Rect, vec![pos2(30., 30.), pos2(150., 120.)], stroke(2.)),
+        (Shapes::Arrow(ArrowHead { double: false, size: 15. }), vec![pos2(180., 180.), pos2(290., 40.)], stroke(3.)),
+        (Shapes::Circle, vec![pos2(80., 160.), pos2(80., 160.)], stroke(2.)),
+    ];
+    assert_golden("half_size_preview", &render(annotations, 0.5));
+}
+
+/// A translucent stroke over a translucent image, checked against source-over in straight alpha.
+#[test]
+fn stroke_over_transparent_pixels() {
+    let canvas = RgbaImage::from_pixel(WIDTH, HEIGHT, Rgba([255, 255, 255, 128]));
+    let line = vec![pos2(20., 100.), pos2(300., 100.)];
+    let color = Color32::from_rgba_unmultiplied(220, 30, 30, 128);
+    let mut painting = Painting::with_annotations(vec2(WIDTH as f32, HEIGHT as f32), 1., vec![(Shapes::None, line, Stroke::new(8., color))]);
+    let (rgba, width, height) = painting.edit_rgba(canvas).expect("edit_rgba returned nothing");
+    let img = RgbaImage::from_raw(width, height, rgba).expect("wrong buffer size");
+
+    //alpha 0.5 + 0.5 * 0.5, each channel (c * 0.5 + 255 * 0.25) / 0.75
+    let expected = [232, 105, 105, 191];
+    let pixel = img.get_pixel(160, 100).0;
+    for (channel, (got, want)) in pixel.iter().zip(expected).enumerate() {
+        assert!(got.abs_diff(want) <= 3, "channel {} is {}, expected {} ({:?})", channel, got, want, pixel);
+    }
+    //away from the line the image is untouched
+    assert_eq!(img.get_pixel(160, 20).0, [255, 255, 255, 128]);
+}
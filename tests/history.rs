@@ -0,0 +1,64 @@
+//! Undo steps made by `History` for edits of the same annotation.
+
+use egui::{pos2, Color32, Stroke};
+use progetto::myapp::historylib::{Annotation, Command, History};
+use progetto::myapp::paintlib::Shapes;
+
+fn rect(width: f32) -> Annotation {
+    (Shapes::Rect, vec![pos2(10., 10.), pos2(50., 40.)], Stroke::new(width, Color32::RED))
+}
+
+/** applica una modifica dello spessore come fa l'editor **/
+fn set_width(history: &mut History, shapes: &mut [Annotation], width: f32) {
+    let before = shapes[0].clone();
+    shapes[0] = rect(width);
+    history.record_edit(0, before, shapes[0].clone(), "Style");
+}
+
+fn new_rect() -> (History, Vec<Annotation>) {
+    let mut history = History::default();
+    let shapes = vec![rect(1.)];
+    history.record(Command::Add(0, shapes[0].clone()));
+    (history, shapes)
+}
+
+#[test]
+fn one_drag_is_one_step() {
+    let (mut history, mut shapes) = new_rect();
+    for width in [2., 3., 4.] {
+        set_width(&mut history, &mut shapes, width);
+    }
+    history.end_edit();
+    assert_eq!(history.position(), 2);
+    history.undo(&mut shapes).unwrap();
+    assert_eq!(shapes[0].2.width, 1.);
+}
+
+#[test]
+fn two_interactions_are_two_steps() {
+    let (mut history, mut shapes) = new_rect();
+    set_width(&mut history, &mut shapes, 2.);
+    set_width(&mut history, &mut shapes, 3.);
+    history.end_edit();
+    set_width(&mut history, &mut shapes, 5.);
+    history.end_edit();
+    assert_eq!(history.names(), vec!["Add □", "Style □", "Style □"]);
+
+    history.undo(&mut shapes).unwrap();
+    assert_eq!(shapes[0].2.width, 3.);
+    history.undo(&mut shapes).unwrap();
+    assert_eq!(shapes[0].2.width, 1.);
+}
+
+#[test]
+fn undo_closes_the_interaction() {
+    let (mut history, mut shapes) = new_rect();
+    set_width(&mut history, &mut shapes, 2.);
+    history.undo(&mut shapes).unwrap();
+    history.redo(&mut shapes).unwrap();
+    //still holding the slider after a redo: the next change must not rewrite the redone step
+    set_width(&mut history, &mut shapes, 6.);
+    assert_eq!(history.position(), 3);
+    history.undo(&mut shapes).unwrap();
+    assert_eq!(shapes[0].2.width, 2.);
+}
@@ -0,0 +1,105 @@
+//! `.shot` projects written and read back, and what happens to a damaged one.
+
+use std::io::Write;
+use std::path::PathBuf;
+use egui::{pos2, vec2, Color32, Stroke};
+use image::{Rgba, RgbaImage};
+use progetto::myapp::capturelib::{CaptureInfo, CaptureMode};
+use progetto::myapp::historylib::{Annotation, Command, History};
+use progetto::myapp::paintlib::{Painting, Shapes};
+use progetto::myapp::projectlib::{project_data, write_project, Project};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("project").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| Rgba([(x * 3) as u8, (y * 5) as u8, 90, if x % 7 == 0 { 128 } else { 255 }]))
+}
+
+fn rect(x: f32, width: f32) -> Annotation {
+    (Shapes::Rect, vec![pos2(x, 10.), pos2(x + 30., 40.)], Stroke::new(width, Color32::RED))
+}
+
+/** progetto di un'immagine tagliata da 80x60 a 50x40, con un'aggiunta e una modifica annullata dopo il taglio **/
+fn painting(image: &RgbaImage, original: &RgbaImage) -> Painting {
+    let arrow = (Shapes::Arrow(Default::default()), vec![pos2(5., 5.), pos2(45., 35.)], Stroke::new(3., Color32::BLUE));
+    let mut painting = Painting::with_annotations(vec2(image.width() as f32, image.height() as f32), 1., vec![rect(2., 2.), arrow.clone()]);
+    let mut history = History::default();
+    history.record(Command::Add(0, rect(12., 2.)));
+    history.record(Command::Crop {
+        image: original.clone(),
+        cropped: image.clone(),
+        shapes: vec![rect(12., 2.)],
+        cropped_shapes: vec![rect(2., 2.)],
+    });
+    history.record(Command::Add(1, arrow));
+    history.record_edit(0, rect(2., 2.), rect(2., 6.), "Style");
+    let mut shapes = vec![rect(2., 2.), rect(2., 6.)];
+    history.undo(&mut shapes);
+    painting.set_history(history);
+    painting
+}
+
+#[test]
+fn project_round_trip() {
+    let dir = dir("round_trip");
+    let original = gradient(80, 60);
+    let image = image::imageops::crop_imm(&original, 10, 5, 50, 40).to_image();
+    let painting = painting(&image, &original);
+    let capture = CaptureInfo { mode: CaptureMode::default(), monitor: Some(2) };
+    let path = dir.join("capture.shot");
+    write_project(&path, &image, &project_data(&painting, capture).unwrap()).unwrap();
+
+    let loaded = Project::load(&path).unwrap();
+    assert!(loaded.image == image, "the image changed");
+    assert_eq!(loaded.capture, capture);
+    assert!(loaded.painting.image_annotations() == painting.image_annotations(), "the annotations changed");
+    let history = loaded.painting.history();
+    assert_eq!(history.names(), painting.history().names());
+    assert_eq!(history.position(), 3);
+    assert!(history.commands() == painting.history().commands(), "the history changed");
+    //the crop can still be undone, back to the full image
+    match &history.commands().0[1] {
+        Command::Crop { image: before, .. } => assert!(*before == original),
+        _ => panic!("the crop is not the second step"),
+    }
+}
+
+#[test]
+fn damaged_projects_are_errors() {
+    let dir = dir("damaged");
+    let image = gradient(20, 10);
+    let painting = Painting::with_annotations(vec2(20., 10.), 1., vec![rect(2., 1.)]);
+    let good = dir.join("good.shot");
+    write_project(&good, &image, &project_data(&painting, CaptureInfo::default()).unwrap()).unwrap();
+    let bytes = std::fs::read(&good).unwrap();
+
+    let truncated = dir.join("truncated.shot");
+    std::fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+    let not_a_zip = dir.join("not_a_zip.shot");
+    std::fs::write(&not_a_zip, b"not a project").unwrap();
+    //a zip with the annotations but without the image
+    let no_image = dir.join("no_image.shot");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&no_image).unwrap());
+    zip.start_file("annotations.json", zip::write::FileOptions::default()).unwrap();
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&good).unwrap()).unwrap();
+    std::io::copy(&mut archive.by_name("annotations.json").unwrap(), &mut zip).unwrap();
+    zip.finish().unwrap();
+    //and one with the image and broken annotations
+    let bad_json = dir.join("bad_json.shot");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&bad_json).unwrap());
+    zip.start_file("image.png", zip::write::FileOptions::default()).unwrap();
+    std::io::copy(&mut archive.by_name("image.png").unwrap(), &mut zip).unwrap();
+    zip.start_file("annotations.json", zip::write::FileOptions::default()).unwrap();
+    zip.write_all(b"{\"version\": 1, \"painting\": ").unwrap();
+    zip.finish().unwrap();
+
+    for path in [&truncated, &not_a_zip, &no_image, &bad_json, &dir.join("missing.shot")] {
+        assert!(Project::load(path).is_err(), "{} was loaded", path.display());
+    }
+    assert!(Project::load(&good).is_ok());
+}
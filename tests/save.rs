@@ -0,0 +1,72 @@
+//! Saves that start together with the same name: each one must end up in its own file.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use image::{Rgba, RgbaImage};
+use progetto::myapp::exportlib::ExportOptions;
+use progetto::myapp::imglib::AllFormats;
+use progetto::myapp::savelib::MySave;
+
+const SAVES: usize = 8;
+
+fn empty_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("progetto-save-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/** immagine diversa per ogni salvataggio, riconoscibile dal colore **/
+fn image(n: usize) -> RgbaImage {
+    RgbaImage::from_pixel(120, 80, Rgba([n as u8 * 20, 255 - n as u8 * 20, 7, 255]))
+}
+
+#[test]
+fn concurrent_saves_keep_every_file() {
+    let dir = empty_dir("concurrent");
+    let barrier = Arc::new(Barrier::new(SAVES));
+    let saves: Vec<_> = (0..SAVES).map(|n| {
+        let (dir, barrier) = (dir.clone(), barrier.clone());
+        thread::spawn(move || {
+            let img = image(n);
+            barrier.wait();
+            let path = MySave::unique_path(dir, "shot", &AllFormats::PNG).unwrap();
+            MySave::write_image(&img, img.width(), img.height(), &path, &AllFormats::PNG, &ExportOptions::default()).unwrap();
+            path
+        })
+    }).collect();
+    let mut paths: Vec<PathBuf> = saves.into_iter().map(|s| s.join().unwrap()).collect();
+    paths.sort();
+    paths.dedup();
+    assert_eq!(paths.len(), SAVES);
+
+    let mut files: Vec<String> = std::fs::read_dir(&dir).unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    files.sort();
+    let mut expected: Vec<String> = (1..SAVES).map(|n| format!("shot_({}).png", n)).collect();
+    expected.push("shot.png".to_string());
+    expected.sort();
+    assert_eq!(files, expected, "no temporary file is left behind");
+
+    let mut colors: Vec<Rgba<u8>> = paths.iter()
+        .map(|p| *image::open(p).unwrap().to_rgba8().get_pixel(0, 0))
+        .collect();
+    colors.sort_by_key(|c| c.0);
+    let mut written: Vec<Rgba<u8>> = (0..SAVES).map(|n| *image(n).get_pixel(0, 0)).collect();
+    written.sort_by_key(|c| c.0);
+    assert_eq!(colors, written);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn failed_save_gives_the_name_back() {
+    let dir = empty_dir("failed");
+    let path = MySave::unique_path(dir.clone(), "shot", &AllFormats::PNG).unwrap();
+    assert!(path.exists(), "the name is reserved before writing");
+    let result = MySave::write_image(&[0; 3], 10, 10, &path, &AllFormats::PNG, &ExportOptions::default());
+    assert!(result.is_err());
+    assert!(!path.exists());
+    assert_eq!(MySave::unique_path(dir.clone(), "shot", &AllFormats::PNG).unwrap(), path);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,171 @@
+//! Transitions of `AppState` without egui or a window, the frame only records what it was asked.
+
+mod common;
+
+use common::TestFrame;
+use egui::{vec2, Vec2};
+use progetto::myapp::Layouts;
+use progetto::myapp::statelib::{AppEvent, AppState, Mode, Overlay};
+
+/// Size of the window in home, different from the default one so restoring it shows.
+const HOME_SIZE: Vec2 = vec2(520., 340.);
+
+fn frame() -> TestFrame {
+    TestFrame::new(HOME_SIZE, vec2(1920., 1080.))
+}
+
+/** stato in home con un'immagine nell'editor **/
+fn editing(frame: &mut TestFrame) -> AppState {
+    let mut state = AppState::default();
+    assert!(state.handle(AppEvent::ImageLoaded, frame));
+    state
+}
+
+#[test]
+fn settings_screens_resize_and_restore() {
+    let mut frame = frame();
+    let mut state = AppState::default();
+    assert!(state.handle(AppEvent::ToggleSettings, &mut frame));
+    assert_eq!(state.mode(), Mode::Home(Overlay::Settings));
+    assert_eq!(frame.size, HOME_SIZE);
+
+    assert!(state.handle(AppEvent::Open(Layouts::Path), &mut frame));
+    assert_eq!(state.mode(), Mode::Path);
+    assert_eq!(frame.size, vec2(400., 480.));
+    //only home can open the other screens
+    assert!(!state.handle(AppEvent::Open(Layouts::About), &mut frame));
+    assert!(!state.handle(AppEvent::ToggleSettings, &mut frame));
+
+    assert!(state.handle(AppEvent::Back, &mut frame));
+    assert_eq!(state.mode(), Mode::Home(Overlay::None));
+    assert_eq!(frame.size, HOME_SIZE);
+    assert!(!state.handle(AppEvent::Back, &mut frame));
+}
+
+#[test]
+fn editor_needs_an_image() {
+    let mut frame = frame();
+    let mut state = AppState::default();
+    for event in [AppEvent::OpenCut, AppEvent::OpenSave, AppEvent::SaveHotkey, AppEvent::Copy] {
+        assert!(!state.handle(event, &mut frame), "{:?} accepted without an image", event);
+    }
+    assert_eq!(state.mode(), Mode::Home(Overlay::None));
+    assert!(!state.is_saving());
+}
+
+#[test]
+fn save_hotkey_refused_while_cutting() {
+    let mut frame = frame();
+    let mut state = editing(&mut frame);
+    assert!(state.handle(AppEvent::OpenCut, &mut frame));
+    assert!(state.cut().is_some());
+    for event in [AppEvent::SaveHotkey, AppEvent::OpenSave, AppEvent::Copy, AppEvent::ToggleSettings] {
+        assert!(!state.handle(event, &mut frame), "{:?} accepted while cutting", event);
+    }
+    assert!(!state.is_saving());
+
+    assert!(state.handle(AppEvent::CloseCut, &mut frame));
+    assert!(state.cut().is_none());
+    assert!(state.handle(AppEvent::SaveHotkey, &mut frame));
+    assert!(state.is_saving());
+}
+
+#[test]
+fn one_hotkey_save_at_a_time() {
+    let mut frame = frame();
+    let mut state = editing(&mut frame);
+    assert!(state.handle(AppEvent::SaveHotkey, &mut frame));
+    assert!(!state.handle(AppEvent::SaveHotkey, &mut frame));
+    //the editor stays usable while saving
+    assert!(state.allows(AppEvent::OpenCut));
+
+    assert!(state.handle(AppEvent::SaveFinished, &mut frame));
+    assert!(!state.is_saving());
+    assert!(state.handle(AppEvent::SaveHotkey, &mut frame));
+}
+
+#[test]
+fn second_save_keeps_the_first_running() {
+    let mut frame = frame();
+    let mut state = editing(&mut frame);
+    assert!(state.handle(AppEvent::SaveHotkey, &mut frame));
+    //the save window can still start another one
+    assert!(state.handle(AppEvent::OpenSave, &mut frame));
+    assert!(state.handle(AppEvent::SaveStarted, &mut frame));
+
+    assert!(state.handle(AppEvent::SaveFinished, &mut frame));
+    assert!(state.is_saving(), "the other save is still running");
+    assert!(!state.handle(AppEvent::SaveHotkey, &mut frame));
+
+    assert!(state.handle(AppEvent::SaveFinished, &mut frame));
+    assert!(!state.is_saving());
+    assert!(state.handle(AppEvent::SaveHotkey, &mut frame));
+}
+
+#[test]
+fn save_window_closes_when_the_save_starts() {
+    let mut frame = frame();
+    let mut state = editing(&mut frame);
+    assert!(state.handle(AppEvent::ToggleSettings, &mut frame));
+    //cut and save replace the settings window
+    assert!(state.handle(AppEvent::OpenSave, &mut frame));
+    assert_eq!(state.mode(), Mode::Home(Overlay::Save));
+    assert!(!state.handle(AppEvent::OpenCut, &mut frame));
+
+    assert!(state.handle(AppEvent::SaveStarted, &mut frame));
+    assert_eq!(state.mode(), Mode::Home(Overlay::None));
+    assert!(state.is_saving());
+}
+
+#[test]
+fn new_capture_hides_and_restores_the_window() {
+    let mut frame = frame();
+    let mut state = editing(&mut frame);
+    assert!(state.handle(AppEvent::OpenCut, &mut frame));
+    assert!(state.handle(AppEvent::NewCapture { time: 2.5 }, &mut frame));
+    assert_eq!(state.mode(), Mode::Capture { back: Layouts::Home });
+    assert_eq!(state.capture_time(), 2.5);
+    assert!(!frame.visible);
+
+    //the overlay resizes the window to the displays
+    frame.size = vec2(1921., 1081.);
+    frame.decorations = false;
+    assert!(state.handle(AppEvent::CaptureDone, &mut frame));
+    assert_eq!(state.mode(), Mode::Home(Overlay::None));
+    assert!(frame.visible && frame.decorations);
+    assert_eq!(frame.size, HOME_SIZE);
+}
+
+#[test]
+fn capture_hotkey_goes_back_where_it_started() {
+    let mut frame = frame();
+    let mut state = AppState::default();
+    assert!(state.handle(AppEvent::ToggleSettings, &mut frame));
+    assert!(state.handle(AppEvent::Open(Layouts::About), &mut frame));
+    frame.minimized = true;
+
+    assert!(state.handle(AppEvent::CaptureHotkey { time: 1. }, &mut frame));
+    assert_eq!(state.mode(), Mode::Capture { back: Layouts::About });
+    assert!(!frame.visible && !frame.minimized);
+
+    frame.size = vec2(1921., 1081.);
+    frame.decorations = false;
+    assert!(state.handle(AppEvent::CaptureHotkey { time: 2. }, &mut frame));
+    assert_eq!(state.mode(), Mode::About);
+    assert!(frame.visible && frame.decorations);
+    assert_eq!(frame.size, vec2(400., 270.));
+
+    assert!(state.handle(AppEvent::Back, &mut frame));
+    assert_eq!(frame.size, HOME_SIZE);
+}
+
+#[test]
+fn capture_hotkey_ignored_while_changing_it() {
+    let mut frame = frame();
+    let mut state = AppState::default();
+    assert!(state.handle(AppEvent::ToggleSettings, &mut frame));
+    assert!(state.handle(AppEvent::Open(Layouts::Hotkey), &mut frame));
+    assert!(!state.handle(AppEvent::CaptureHotkey { time: 1. }, &mut frame));
+    assert_eq!(state.mode(), Mode::Hotkey);
+    assert!(frame.visible);
+}
@@ -0,0 +1,53 @@
+//! `Painting::vector_image` and `encode_svg`: what a redaction covers must not survive as an SVG element.
+
+mod common;
+
+use common::{painting, stroke, HEIGHT, WIDTH};
+use egui::pos2;
+use image::{Rgba, RgbaImage};
+use progetto::myapp::historylib::Annotation;
+use progetto::myapp::paintlib::{ArrowHead, Redaction, Shapes, TextBox};
+use progetto::myapp::svglib::encode_svg;
+
+fn text(text: &str) -> Shapes {
+    Shapes::Text(TextBox { text: text.to_string(), size: 32., background: None })
+}
+
+/** svg delle annotazioni, in pixel dell'immagine, su uno sfondo grigio **/
+fn export(annotations: Vec<Annotation>) -> (String, RgbaImage) {
+    let vector = painting(annotations, 1.).vector_image(RgbaImage::from_pixel(WIDTH, HEIGHT, Rgba([200, 200, 200, 255])));
+    let mut svg = vec![];
+    encode_svg(&vector, &mut svg).unwrap();
+    (String::from_utf8(svg).unwrap(), vector.background)
+}
+
+#[test]
+fn redacted_text_is_not_in_the_file() {
+    let annotations = vec![
+        (text("SECRET"), vec![pos2(20., 20.)], stroke(1.)),
+        (text("public"), vec![pos2(20., 130.)], stroke(1.)),
+        (Shapes::Redact(Redaction::BlackBox), vec![pos2(10., 10.), pos2(200., 80.)], stroke(1.)),
+    ];
+    let (svg, background) = export(annotations);
+    assert!(!svg.contains("SECRET"), "redacted text exported as an element:\n{}", svg);
+    assert!(svg.contains("public"), "text outside the redaction should stay editable");
+    //burned in and then covered
+    assert_eq!(*background.get_pixel(60, 40), Rgba([0, 0, 0, 255]));
+}
+
+#[test]
+fn shapes_drawn_after_a_redaction_stay_vector() {
+    let arrow = Shapes::Arrow(ArrowHead { double: false, size: 15. });
+    let annotations = vec![
+        (Shapes::Rect, vec![pos2(30., 30.), pos2(150., 120.)], stroke(4.)),
+        (Shapes::Redact(Redaction::Pixelate), vec![pos2(100., 20.), pos2(220., 140.)], stroke(1.)),
+        (arrow, vec![pos2(20., 180.), pos2(200., 60.)], stroke(3.)),
+    ];
+    let (svg, background) = export(annotations);
+    assert!(!svg.contains("<rect x=\"30\""), "the covered rectangle should be in the bitmap:\n{}", svg);
+    assert_eq!(svg.matches("<path").count(), 1, "the arrow drawn over the redaction should stay a path");
+    //left edge of the rectangle, outside the redaction
+    assert_ne!(*background.get_pixel(30, 70), Rgba([200, 200, 200, 255]));
+    //same caps and joins as the bitmap export
+    assert!(svg.contains(r#"stroke-linecap="butt" stroke-linejoin="miter""#));
+}
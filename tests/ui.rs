@@ -0,0 +1,334 @@
+//! Navigation between the layouts, driven without a window: every step is one `egui` frame,
+//! widgets are found by their label in the AccessKit tree and clicked with pointer events.
+
+mod common;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use common::TestFrame;
+use egui::{pos2, vec2, Context, Event, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, Vec2};
+use global_hotkey::hotkey::HotKey;
+use image::{Rgba, RgbaImage};
+use progetto::myapp::capturelib::FakeBackend;
+use progetto::myapp::hotkeylib::HotKeyRegistry;
+use progetto::myapp::statelib::{Mode, Overlay};
+use progetto::myapp::{Config, Layouts, MyApp};
+
+/// Size of the window when the application starts, as in `main`.
+const START_SIZE: Vec2 = vec2(400., 200.);
+const DISPLAY: (u32, u32) = (640, 400);
+
+/// Hotkeys currently registered, instead of the global ones of the desktop.
+#[derive(Clone, Default)]
+struct TestRegistry(Arc<Mutex<Vec<HotKey>>>);
+
+impl TestRegistry {
+    fn registered(&self) -> Vec<HotKey> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl HotKeyRegistry for TestRegistry {
+    fn register(&self, hotkey: HotKey) -> global_hotkey::Result<()> {
+        self.0.lock().unwrap().push(hotkey);
+        Ok(())
+    }
+
+    fn unregister(&self, hotkey: HotKey) -> global_hotkey::Result<()> {
+        self.0.lock().unwrap().retain(|h| *h != hotkey);
+        Ok(())
+    }
+}
+
+/** un solo schermo con un gradiente, cosí un taglio cambia l'immagine salvata **/
+fn display() -> FakeBackend {
+    FakeBackend::from_images(vec![RgbaImage::from_fn(DISPLAY.0, DISPLAY.1, |x, y| Rgba([(x % 256) as u8, (y % 256) as u8, 90, 255]))])
+}
+
+struct Harness {
+    ctx: Context,
+    app: MyApp,
+    frame: TestFrame,
+    hotkeys: TestRegistry,
+    runtime: tokio::runtime::Runtime,
+    time: f64,
+    events: Vec<Event>,
+    pointer: Pos2,
+    /// labelled widgets of the last frame with their rect
+    widgets: Vec<(String, Rect)>,
+    dir: PathBuf,
+}
+
+impl Harness {
+    /** applicazione nuova con i salvataggi in una cartella temporanea per ogni test **/
+    fn new(name: &str) -> Self {
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("ui").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("shots")).unwrap();
+
+        //settings and capture history stay in the test folder, never in the real ones
+        let config = Config { path: dir.clone(), file: dir.join("config.toml"), ..Config::default() };
+        let hotkeys = TestRegistry::default();
+        let app = MyApp::new(config, Box::new(display()), Box::new(hotkeys.clone()), None);
+        let ctx = Context::default();
+        ctx.enable_accesskit();
+        let frame = TestFrame::new(START_SIZE, vec2(DISPLAY.0 as f32, DISPLAY.1 as f32));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut harness = Self {
+            ctx, app, frame, hotkeys, runtime, time: 0., events: vec![], pointer: Pos2::ZERO, widgets: vec![], dir,
+        };
+        harness.step();
+        harness
+    }
+
+    /** un frame, con gli eventi accumulati e la finestra della dimensione chiesta dall'applicazione **/
+    fn step(&mut self) {
+        self.time += 0.1;
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, self.frame.size)),
+            time: Some(self.time),
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+        let _guard = self.runtime.enter();
+        let (app, frame) = (&mut self.app, &mut self.frame);
+        let output = self.ctx.run(input, |ctx| app.show(ctx, frame));
+        self.widgets = output.platform_output.accesskit_update
+            .map(|update| {
+                update.nodes
+                    .iter()
+                    .filter_map(|(_, node)| {
+                        let bounds = node.bounds()?;
+                        let rect = Rect::from_min_max(pos2(bounds.x0 as f32, bounds.y0 as f32), pos2(bounds.x1 as f32, bounds.y1 as f32));
+                        Some((node.name()?.to_string(), rect))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
+    fn steps(&mut self, n: usize) {
+        for _ in 0..n {
+            self.step();
+        }
+    }
+
+    fn find(&self, label: &str) -> Rect {
+        match self.widgets.iter().find(|(name, _)| name == label) {
+            Some((_, rect)) => *rect,
+            None => {
+                let names: Vec<&str> = self.widgets.iter().map(|(name, _)| name.as_str()).collect();
+                panic!("no widget '{}' in {:?} (layout {:?})", label, names, self.app.state.layout());
+            }
+        }
+    }
+
+    fn move_to(&mut self, pos: Pos2) {
+        self.pointer = pos;
+        self.events.push(Event::PointerMoved(pos));
+        self.step();
+    }
+
+    fn button(&mut self, pressed: bool) {
+        self.events.push(Event::PointerButton { pos: self.pointer, button: PointerButton::Primary, pressed, modifiers: Modifiers::NONE });
+        self.step();
+    }
+
+    /** l'ultimo tra i widget con la stessa etichetta, dall'alto verso il basso **/
+    fn lowest(&self, label: &str) -> Rect {
+        self.find(label);
+        self.widgets
+            .iter()
+            .filter(|(name, _)| name == label)
+            .map(|(_, rect)| *rect)
+            .max_by(|a, b| a.min.y.total_cmp(&b.min.y))
+            .unwrap()
+    }
+
+    fn click(&mut self, label: &str) {
+        self.click_where(|harness| harness.find(label));
+    }
+
+    fn click_where(&mut self, locate: impl Fn(&Harness) -> Rect) {
+        //a window that just opened can still be moving into the screen
+        let mut pos = locate(self).center();
+        for _ in 0..10 {
+            self.move_to(pos);
+            let now = locate(self).center();
+            if now == pos {
+                break;
+            }
+            pos = now;
+        }
+        self.button(true);
+        self.button(false);
+        //what opens on click (menus, windows) shows up in the next frame
+        self.step();
+    }
+
+    fn drag(&mut self, from: Pos2, to: Pos2) {
+        self.move_to(from);
+        self.button(true);
+        for i in 1..=5 {
+            self.move_to(from + (to - from) * i as f32 / 5.);
+        }
+        self.button(false);
+    }
+
+    fn key(&mut self, key: Key, modifiers: Modifiers) {
+        for pressed in [true, false] {
+            self.events.push(Event::Key { key, pressed, repeat: false, modifiers });
+            self.step();
+        }
+    }
+
+    /** aspetta che il salvataggio fatto con tokio finisca e arrivi la notifica **/
+    fn wait_notification(&mut self) -> String {
+        for _ in 0..200 {
+            if let Some(notification) = &self.app.notification {
+                assert!(!notification.error, "{}", notification.text);
+                return notification.text.clone();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            self.step();
+        }
+        panic!("the save did not finish");
+    }
+}
+
+fn assert_layout(harness: &Harness, expected: Layouts) {
+    assert_eq!(harness.app.state.layout(), expected);
+}
+
+/** apre la finestra delle impostazioni e il menu `menu` al suo interno **/
+fn open_settings_menu(harness: &mut Harness, menu: &str) {
+    harness.click("⚙");
+    assert_eq!(harness.app.state.mode(), Mode::Home(Overlay::Settings));
+    harness.click(menu);
+}
+
+#[test]
+fn settings_change_path() {
+    let mut harness = Harness::new("settings_change_path");
+    open_settings_menu(&mut harness, "Path");
+    harness.click("Change Path");
+    assert_layout(&harness, Layouts::Path);
+    assert_ne!(harness.app.state.mode(), Mode::Home(Overlay::Settings));
+    assert_eq!(harness.frame.size, vec2(400., 480.));
+
+    harness.click("🗁 shots");
+    //the button confirms, the collapsing header above has the same text
+    harness.click_where(|harness| harness.lowest("Change Path"));
+    assert_layout(&harness, Layouts::Home);
+    assert_eq!(harness.app.config.path, harness.dir.join("shots"));
+    let stored = Config::load(harness.dir.join("config.toml"));
+    assert_eq!(stored.path, harness.dir.join("shots"));
+    //the window goes back to the size it had before the settings
+    assert_eq!(harness.frame.size, START_SIZE);
+}
+
+#[test]
+fn settings_path_back_keeps_path() {
+    let mut harness = Harness::new("settings_path_back_keeps_path");
+    open_settings_menu(&mut harness, "Path");
+    harness.click("Change Path");
+    harness.click("🗁 shots");
+    harness.click("↩");
+    assert_layout(&harness, Layouts::Home);
+    assert_eq!(harness.app.config.path, harness.dir);
+    assert_eq!(harness.frame.size, START_SIZE);
+
+    //the discarded folder is not proposed again
+    open_settings_menu(&mut harness, "Path");
+    harness.click("Change Path");
+    harness.find("🗁 shots");
+}
+
+#[test]
+fn change_hotkey_cancel() {
+    let mut harness = Harness::new("change_hotkey_cancel");
+    let config = harness.app.config.take_screenshot.clone();
+    let before = harness.hotkeys.registered();
+    assert_eq!(before.len(), 2);
+
+    open_settings_menu(&mut harness, "Hot Keys");
+    harness.click("Change HotKey");
+    assert_layout(&harness, Layouts::Hotkey);
+    assert_eq!(harness.frame.size, vec2(400., 500.));
+
+    //the key typed while the form is enabled becomes the code of the hotkey
+    harness.key(Key::K, Modifiers::NONE);
+    harness.find("K");
+    harness.click("Register");
+    harness.find("The hotkey you have registered is : ");
+    assert_eq!(harness.hotkeys.registered().len(), 2);
+    assert!(harness.hotkeys.registered().iter().all(|h| h.id() != config.0), "the old hotkey is still registered");
+
+    harness.click("Cancel");
+    harness.click("↩");
+    assert_layout(&harness, Layouts::Home);
+    assert_eq!(harness.app.config.take_screenshot, config);
+    let mut after = harness.hotkeys.registered();
+    after.sort_by_key(|h| h.id());
+    let mut before = before;
+    before.sort_by_key(|h| h.id());
+    assert_eq!(after, before);
+    assert_eq!(harness.frame.size, START_SIZE);
+}
+
+#[test]
+fn screenshot_cut_save() {
+    let mut harness = Harness::new("screenshot_cut_save");
+    harness.click("+ New");
+    assert_layout(&harness, Layouts::Screenshot);
+    //the window hides while the delay runs, then covers the display
+    harness.step();
+    assert!(harness.frame.visible);
+    assert!(!harness.frame.decorations);
+    assert_eq!(harness.frame.size, vec2(DISPLAY.0 as f32 + 1., DISPLAY.1 as f32 + 1.));
+
+    harness.click("🖵");
+    harness.steps(3);
+    assert_layout(&harness, Layouts::Home);
+    assert!(harness.frame.decorations);
+    let texture = harness.app.texture.as_ref().expect("no screenshot in the editor");
+    assert_eq!(texture.size(), [DISPLAY.0 as usize, DISPLAY.1 as usize]);
+
+    //the right side of the cut rectangle dragged to the middle of the image
+    harness.click("✂");
+    let image = harness.find("Screenshot");
+    harness.drag(pos2(image.max.x - 2., image.center().y), image.center());
+    harness.click("✔");
+    harness.steps(2);
+    let [width, height] = harness.app.texture.as_ref().unwrap().size();
+    assert!((width as i32 - DISPLAY.0 as i32 / 2).abs() <= 4, "cut to {} pixels", width);
+    assert_eq!(height, DISPLAY.1 as usize);
+
+    //opening and closing the save window does not use up a counter value
+    harness.click("💾");
+    harness.click("Cancel");
+    assert_eq!(harness.app.state.mode(), Mode::Home(Overlay::None));
+    assert_eq!(harness.app.config.counter, 0);
+
+    harness.click("💾");
+    assert_eq!(harness.app.state.mode(), Mode::Home(Overlay::Save));
+    assert_eq!(harness.app.config.counter, 0);
+    harness.click("Save");
+    let text = harness.wait_notification();
+    assert_ne!(harness.app.state.mode(), Mode::Home(Overlay::Save));
+    assert_eq!(harness.app.config.counter, 1);
+    assert_eq!(Config::load(harness.dir.join("config.toml")).counter, 1);
+
+    let saved: Vec<PathBuf> = std::fs::read_dir(&harness.dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "png"))
+        .collect();
+    assert_eq!(saved.len(), 1, "{}", text);
+    let img = image::open(&saved[0]).unwrap();
+    assert_eq!((img.width() as usize, img.height() as usize), (width, height));
+    let index = std::fs::read_to_string(harness.dir.join("captures.json")).unwrap();
+    assert!(index.contains(saved[0].file_name().unwrap().to_str().unwrap()), "{}", index);
+}
+
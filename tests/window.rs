@@ -0,0 +1,63 @@
+//! Choice of the windows offered by the window picker, from what X11 answered, without a display.
+
+use progetto::myapp::capturelib::ScreenRect;
+use progetto::myapp::windowlib::{capturable, window_at, StackedWindow, WindowInfo};
+
+const OWN_PID: u32 = 4242;
+
+fn rect(x: i32, y: i32, width: u32, height: u32) -> ScreenRect {
+    ScreenRect { x, y, width, height }
+}
+
+/** finestra con 30 pixel di barra del titolo sopra al client **/
+fn window(id: u32, frame: ScreenRect, pid: Option<u32>) -> Option<StackedWindow> {
+    let client = rect(frame.x, frame.y + 30, frame.width, frame.height.saturating_sub(30));
+    Some(StackedWindow { info: WindowInfo { id, title: format!("window {}", id), frame, client }, pid })
+}
+
+fn ids(windows: &[WindowInfo]) -> Vec<u32> {
+    windows.iter().map(|w| w.id).collect()
+}
+
+#[test]
+fn topmost_window_first() {
+    let stack = [
+        window(1, rect(0, 0, 800, 600), Some(10)),
+        window(2, rect(100, 100, 400, 300), None),
+        window(3, rect(200, 150, 400, 300), Some(11)),
+    ];
+    assert_eq!(ids(&capturable(&stack, OWN_PID)), vec![3, 2, 1]);
+}
+
+#[test]
+fn hidden_silent_and_own_windows_are_left_out() {
+    let stack = [
+        window(1, rect(0, 0, 800, 600), Some(10)),
+        //not viewable, or closed while listing
+        None,
+        window(2, rect(100, 100, 400, 300), Some(OWN_PID)),
+        //input-only helpers of some toolkits
+        window(3, rect(-1, -1, 1, 1), Some(12)),
+        None,
+        window(4, rect(50, 50, 200, 200), None),
+    ];
+    assert_eq!(ids(&capturable(&stack, OWN_PID)), vec![4, 1]);
+}
+
+#[test]
+fn pointer_picks_the_window_on_top() {
+    let stack = [
+        window(1, rect(0, 0, 800, 600), Some(10)),
+        window(2, rect(100, 100, 400, 300), Some(11)),
+        window(3, rect(1000, 0, 300, 300), Some(OWN_PID)),
+    ];
+    let windows = capturable(&stack, OWN_PID);
+    assert_eq!(window_at(&windows, 150, 110).map(|w| w.id), Some(2));
+    //the title bar belongs to the frame
+    assert_eq!(window_at(&windows, 150, 105).map(|w| w.client.y), Some(130));
+    assert_eq!(window_at(&windows, 50, 50).map(|w| w.id), Some(1));
+    assert_eq!(window_at(&windows, 499, 399).map(|w| w.id), Some(2));
+    assert_eq!(window_at(&windows, 500, 400).map(|w| w.id), Some(1));
+    //our own window is transparent to the picker
+    assert_eq!(window_at(&windows, 1100, 100), None);
+}